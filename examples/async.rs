@@ -8,7 +8,7 @@ LOAD 3
 SWAP 0, 2
 LOAD 3
 SWAP 0, 3
-LOAD 3ß
+LOAD 3
 ";
 
     println!("🤖 Assembly program:");
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use superoptimusprime::cpu::Instruction;
+
+// parsing must reject bad input with an error, never panic, and whatever parses has to print
+// back as text that parses to the same instruction
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(instruction) = text.parse::<Instruction>() {
+            assert_eq!(
+                instruction.to_string().parse::<Instruction>(),
+                Ok(instruction)
+            );
+        }
+    }
+});
@@ -0,0 +1,342 @@
+use std::collections::BTreeSet;
+
+use crate::cpu::{validate, ExecError, Instruction};
+use crate::isa::InstructionSemantics;
+
+// the instructions that may run after the one at `index`; `program.len()` is the end
+pub fn successors<I: InstructionSemantics>(program: &[I], index: usize) -> Vec<usize> {
+    let instruction = &program[index];
+    let mut next = Vec::new();
+    if instruction.falls_through() {
+        next.push(index + 1);
+    }
+    if let Some(target) = instruction.jump_target() {
+        let target = target.min(program.len());
+        if !next.contains(&target) {
+            next.push(target);
+        }
+    }
+    next
+}
+
+// which cells may still be read before each instruction, and last at the end, where only the
+// `outputs` count
+pub fn live_cells<I: InstructionSemantics>(
+    program: &[I],
+    cells: usize,
+    outputs: &[usize],
+) -> Vec<Vec<bool>> {
+    let mut live = vec![vec![false; cells]; program.len() + 1];
+    for &cell in outputs {
+        live[program.len()][cell] = true;
+    }
+
+    // jumps back carry cells around loops, so passes repeat until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in (0..program.len()).rev() {
+            let mut before = live_after(program, &live, index);
+            for cell in program[index].writes() {
+                before[cell] = false;
+            }
+            for cell in program[index].reads() {
+                before[cell] = true;
+            }
+            if before != live[index] {
+                live[index] = before;
+                changed = true;
+            }
+        }
+    }
+    live
+}
+
+// the cells live after the instruction at `index`, given what `live_cells` found
+pub fn live_after<I: InstructionSemantics>(
+    program: &[I],
+    live: &[Vec<bool>],
+    index: usize,
+) -> Vec<bool> {
+    let mut after = vec![false; live[index].len()];
+    for successor in successors(program, index) {
+        for (after, &live) in after.iter_mut().zip(&live[successor]) {
+            *after |= live;
+        }
+    }
+    after
+}
+
+// for each cell, the instructions whose value it may hold before each instruction, and last at
+// the end; `None` stands for the value the cell started with
+pub fn reaching_definitions<I: InstructionSemantics>(
+    program: &[I],
+    cells: usize,
+) -> Vec<Vec<BTreeSet<Option<usize>>>> {
+    let mut reaching = vec![vec![BTreeSet::new(); cells]; program.len() + 1];
+    reaching[0] = vec![BTreeSet::from([None]); cells];
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..program.len() {
+            let mut after = reaching[index].clone();
+            for cell in program[index].writes() {
+                after[cell] = BTreeSet::from([Some(index)]);
+            }
+            for successor in successors(program, index) {
+                for (before, definitions) in reaching[successor].iter_mut().zip(&after) {
+                    let known = before.len();
+                    before.extend(definitions);
+                    changed |= before.len() != known;
+                }
+            }
+        }
+    }
+    reaching
+}
+
+// a value flowing from where it was written to where it's read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DefUse {
+    // the reading instruction, or the program's length for an output read at the end
+    pub user: usize,
+    pub cell: usize,
+    // the writing instruction, or `None` for the value the cell started with
+    pub definition: Option<usize>,
+}
+
+// every value any instruction or output may read, ordered by reader
+pub fn def_use_chains<I: InstructionSemantics>(
+    program: &[I],
+    cells: usize,
+    outputs: &[usize],
+) -> Vec<DefUse> {
+    let reaching = reaching_definitions(program, cells);
+    let uses = program
+        .iter()
+        .enumerate()
+        .flat_map(|(index, instruction)| {
+            instruction
+                .reads()
+                .into_iter()
+                .map(move |cell| (index, cell))
+        })
+        .chain(outputs.iter().map(|&cell| (program.len(), cell)));
+
+    let mut chains = BTreeSet::new();
+    for (user, cell) in uses {
+        for &definition in &reaching[user][cell] {
+            chains.insert(DefUse {
+                user,
+                cell,
+                definition,
+            });
+        }
+    }
+    chains.into_iter().collect()
+}
+
+// the instructions whose writes nothing reads before they are overwritten or the program ends
+pub fn dead_stores<I: InstructionSemantics>(
+    program: &[I],
+    cells: usize,
+    outputs: &[usize],
+) -> Vec<usize> {
+    let live = live_cells(program, cells, outputs);
+    (0..program.len())
+        .filter(|&index| {
+            let after = live_after(program, &live, index);
+            program[index].jump_target().is_none()
+                && program[index].writes().iter().all(|&cell| !after[cell])
+        })
+        .collect()
+}
+
+// removes dead stores, and the stores only they read, keeping jumps pointing at the same
+// instructions; the result leaves the `outputs` as `program` does
+pub fn eliminate_dead_stores(
+    program: &[Instruction],
+    cells: usize,
+    outputs: &[usize],
+) -> Result<Vec<Instruction>, ExecError> {
+    validate(program, cells)?;
+    let mut program = program.to_vec();
+    loop {
+        let dead = dead_stores(&program, cells, outputs);
+        if dead.is_empty() {
+            return Ok(program);
+        }
+
+        // a jump to a removed instruction lands on the one after it
+        let renumber =
+            |target: usize| target - dead.iter().filter(|&&index| index < target).count();
+        program = program
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !dead.contains(index))
+            .map(|(_, instruction)| match *instruction {
+                Instruction::Jmp(target) => Instruction::Jmp(renumber(target)),
+                Instruction::Jz(memory, target) => Instruction::Jz(memory, renumber(target)),
+                Instruction::Jnz(memory, target) => Instruction::Jnz(memory, renumber(target)),
+                instruction => instruction,
+            })
+            .collect();
+    }
+}
+
+// the def-use chains as a Graphviz digraph: one node per instruction, initial value and output,
+// with an edge per value labelled by its cell
+pub fn dependency_graph<I: InstructionSemantics>(
+    program: &[I],
+    cells: usize,
+    outputs: &[usize],
+) -> String {
+    let chains = def_use_chains(program, cells, outputs);
+    let inputs: BTreeSet<usize> = chains
+        .iter()
+        .filter(|chain| chain.definition.is_none())
+        .map(|chain| chain.cell)
+        .collect();
+
+    let mut lines = vec![
+        "digraph dependencies {".to_string(),
+        "  node [shape=box];".to_string(),
+    ];
+    for cell in &inputs {
+        lines.push(format!(
+            "  in{} [label=\"c{}\", shape=ellipse];",
+            cell, cell
+        ));
+    }
+    for (index, instruction) in program.iter().enumerate() {
+        let label = format!("{}: {}", index, instruction).replace('"', "\\\"");
+        lines.push(format!("  i{} [label=\"{}\"];", index, label));
+    }
+    for cell in outputs {
+        lines.push(format!(
+            "  out{} [label=\"c{}\", shape=doublecircle];",
+            cell, cell
+        ));
+    }
+    for chain in &chains {
+        let from = match chain.definition {
+            Some(index) => format!("i{}", index),
+            None => format!("in{}", chain.cell),
+        };
+        let to = match chain.user == program.len() {
+            true => format!("out{}", chain.cell),
+            false => format!("i{}", chain.user),
+        };
+        lines.push(format!("  {} -> {} [label=\"c{}\"];", from, to, chain.cell));
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::verify;
+
+    fn chain(user: usize, cell: usize, definition: Option<usize>) -> DefUse {
+        DefUse {
+            user,
+            cell,
+            definition,
+        }
+    }
+
+    #[test]
+    fn finds_live_cells_around_loops() {
+        let program = parse("LOAD 1\nMOV 1, 0").unwrap();
+        assert_eq!(
+            live_cells(&program, 2, &[0, 1]),
+            vec![vec![false, false], vec![true, false], vec![true, true]]
+        );
+        assert_eq!(
+            live_cells(&program, 2, &[1]),
+            vec![vec![false, false], vec![true, false], vec![false, true]]
+        );
+
+        // cell 1 is read on the next trip around the loop
+        let program = parse("LOAD 3\nADD 1, 0\nDEC 0\nJNZ 0, 1\nLOAD 0").unwrap();
+        let live = live_cells(&program, 2, &[1]);
+        assert_eq!(live[0], vec![false, true]);
+        assert_eq!(live[3], vec![true, true]);
+        assert_eq!(live[4], vec![false, true]);
+        assert_eq!(successors(&program, 3), vec![4, 1]);
+        assert_eq!(successors(&parse("JMP 0").unwrap(), 0), vec![0]);
+    }
+
+    #[test]
+    fn chains_definitions_to_uses() {
+        let program = parse("LOAD 3\nADD 1, 0\nDEC 0\nJNZ 0, 1").unwrap();
+        assert_eq!(
+            def_use_chains(&program, 2, &[1]),
+            vec![
+                chain(1, 0, Some(0)),
+                chain(1, 0, Some(2)),
+                chain(1, 1, None),
+                chain(1, 1, Some(1)),
+                chain(2, 0, Some(0)),
+                chain(2, 0, Some(2)),
+                chain(3, 0, Some(2)),
+                chain(4, 1, Some(1)),
+            ]
+        );
+        assert_eq!(
+            def_use_chains(&parse("SWAP 0, 1").unwrap(), 2, &[0]),
+            vec![chain(0, 0, None), chain(0, 1, None), chain(1, 0, Some(0))]
+        );
+    }
+
+    #[test]
+    fn eliminates_dead_stores() {
+        let program = parse("LOAD 1\nMOV 1, 0\nINC 0\nLOAD 2\nADD 1, 0").unwrap();
+        assert_eq!(dead_stores(&program, 2, &[1]), vec![2]);
+        assert_eq!(
+            eliminate_dead_stores(&program, 2, &[1]).unwrap(),
+            parse("LOAD 1\nMOV 1, 0\nLOAD 2\nADD 1, 0").unwrap()
+        );
+        // once the ADD goes, nothing reads the LOADs before it
+        assert_eq!(
+            eliminate_dead_stores(&program, 2, &[0]).unwrap(),
+            parse("LOAD 2").unwrap()
+        );
+
+        let program = parse("LOAD 3\nMOV 2, 0\nINC 1\nDEC 0\nJNZ 0, 2").unwrap();
+        let eliminated = eliminate_dead_stores(&program, 3, &[1]).unwrap();
+        assert_eq!(eliminated, parse("LOAD 3\nINC 1\nDEC 0\nJNZ 0, 1").unwrap());
+        assert_eq!(
+            verify::run(&eliminated, &[0, 4, 0], 100).map(|state| state[1]),
+            verify::run(&program, &[0, 4, 0], 100).map(|state| state[1])
+        );
+        assert!(eliminate_dead_stores(&program, 2, &[1]).is_err());
+    }
+
+    #[test]
+    fn exports_dependency_graphs() {
+        let program = parse("LOAD 2\nXOR 1, 0\nINC 0").unwrap();
+        assert_eq!(
+            dependency_graph(&program, 2, &[1]),
+            [
+                "digraph dependencies {",
+                "  node [shape=box];",
+                "  in1 [label=\"c1\", shape=ellipse];",
+                "  i0 [label=\"0: LOAD 2\"];",
+                "  i1 [label=\"1: XOR 1, 0\"];",
+                "  i2 [label=\"2: INC 0\"];",
+                "  out1 [label=\"c1\", shape=doublecircle];",
+                "  i0 -> i1 [label=\"c0\"];",
+                "  in1 -> i1 [label=\"c1\"];",
+                "  i0 -> i2 [label=\"c0\"];",
+                "  i1 -> out1 [label=\"c1\"];",
+                "}",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::config::{SearchConfig, SearchLimits};
+use crate::cpu::{Instruction, CPU};
+use crate::encoding::required_cells;
+use crate::parser::Assembly;
+use crate::superoptimizer::{self, Stopped};
+
+// bumped whenever a field of the batch report changes meaning or is removed
+pub const BATCH_REPORT_VERSION: u32 = 1;
+
+// candidates a search tries between two looks at the shared budget
+const BUDGET_CHUNK: usize = 1024;
+
+// time and candidate programs shared by every search of a batch
+pub struct Budget {
+    deadline: Option<Instant>,
+    candidates: Option<AtomicUsize>,
+}
+
+impl Budget {
+    pub fn new(time: Option<Duration>, candidates: Option<usize>) -> Budget {
+        Budget {
+            deadline: time.map(|time| Instant::now() + time),
+            candidates: candidates.map(AtomicUsize::new),
+        }
+    }
+
+    pub fn unlimited() -> Budget {
+        Budget::new(None, None)
+    }
+
+    // takes `candidates` out of the budget, or all that's left of it; false once it's used up
+    pub fn spend(&self, candidates: usize) -> bool {
+        let afforded = match &self.candidates {
+            Some(remaining) => {
+                let before = remaining
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                        Some(remaining.saturating_sub(candidates))
+                    })
+                    .unwrap();
+                before >= candidates
+            }
+            None => true,
+        };
+        afforded
+            && self
+                .deadline
+                .is_none_or(|deadline| Instant::now() < deadline)
+    }
+}
+
+// one program to optimize; `assembly` holds why it couldn't be read, if it couldn't
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub name: String,
+    pub assembly: Result<Assembly, String>,
+}
+
+// the files directly inside `directory`, by name, to batch together
+pub fn programs_in(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchOptions {
+    pub limits: SearchLimits,
+    // immediates are searched by signed magnitude
+    pub signed: bool,
+    pub time_budget: Option<Duration>,
+    pub candidate_budget: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchEntry {
+    pub name: String,
+    pub original_length: Option<usize>,
+    // the input itself when nothing shorter was found
+    pub optimized_length: Option<usize>,
+    pub savings: usize,
+    pub program: Option<Vec<Instruction>>,
+    pub elapsed_micros: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BatchReport {
+    pub version: u32,
+    pub entries: Vec<BatchEntry>,
+    pub elapsed_micros: u64,
+}
+
+// runs every job in parallel until done or the budget runs out; entries keep the jobs' order
+pub fn optimize_batch(jobs: &[BatchJob], options: &BatchOptions) -> BatchReport {
+    let start = Instant::now();
+    let budget = Budget::new(options.time_budget, options.candidate_budget);
+    let entries = jobs
+        .par_iter()
+        .map(|job| optimize_job(job, options, &budget))
+        .collect();
+
+    BatchReport {
+        version: BATCH_REPORT_VERSION,
+        entries,
+        elapsed_micros: start.elapsed().as_micros() as u64,
+    }
+}
+
+fn optimize_job(job: &BatchJob, options: &BatchOptions, budget: &Budget) -> BatchEntry {
+    let start = Instant::now();
+    let mut entry = BatchEntry {
+        name: job.name.clone(),
+        original_length: None,
+        optimized_length: None,
+        savings: 0,
+        program: None,
+        elapsed_micros: 0,
+        error: None,
+    };
+
+    match optimize_assembly(job, options, budget) {
+        Ok((original, optimized)) => {
+            entry.original_length = Some(original.len());
+            entry.optimized_length = Some(optimized.len());
+            entry.savings = original.len().saturating_sub(optimized.len());
+            entry.program = Some(optimized);
+        }
+        Err((original_length, error)) => {
+            entry.original_length = original_length;
+            entry.error = Some(error);
+        }
+    }
+    entry.elapsed_micros = start.elapsed().as_micros() as u64;
+    entry
+}
+
+type JobResult = Result<(Vec<Instruction>, Vec<Instruction>), (Option<usize>, String)>;
+
+// the original and the shortest equivalent program
+fn optimize_assembly(job: &BatchJob, options: &BatchOptions, budget: &Budget) -> JobResult {
+    let assembly = job
+        .assembly
+        .as_ref()
+        .map_err(|error| (None, error.clone()))?;
+    let program = &assembly.program;
+    let failed = |error: String| (Some(program.len()), error);
+
+    let cells = assembly.cells.unwrap_or_else(|| required_cells(program));
+    let config = SearchConfig::covering(program, cells, options.signed).limited(&options.limits);
+    let target = match &assembly.target {
+        Some(target) => target.clone(),
+        None => {
+            let mut cpu = CPU::new(cells).with_max_steps(config.max_steps);
+            cpu.try_execute(program)
+                .map_err(|error| failed(error.to_string()))?;
+            cpu.state
+        }
+    };
+
+    match search(&config, &target, budget) {
+        Ok(Some(found)) if found.len() < program.len() || assembly.target.is_some() => {
+            Ok((program.clone(), found))
+        }
+        Ok(_) if assembly.target.is_none() => Ok((program.clone(), program.clone())),
+        Ok(_) => Err(failed("no program reaches the target".to_string())),
+        Err(Stopped) => Err(failed("budget exhausted".to_string())),
+    }
+}
+
+// the shortest program reaching `target_state`, taking what it tries out of `budget`
+fn search(
+    config: &SearchConfig,
+    target_state: &[usize],
+    budget: &Budget,
+) -> Result<Option<Vec<Instruction>>, Stopped> {
+    let mut unpaid = 0;
+    let result = superoptimizer::superoptimize_while(config, target_state, || {
+        unpaid += 1;
+        if unpaid < BUDGET_CHUNK {
+            return true;
+        }
+        unpaid = 0;
+        budget.spend(BUDGET_CHUNK)
+    });
+    // the candidates since the last full chunk count too
+    budget.spend(unpaid);
+    result
+}
+
+impl BatchReport {
+    pub fn total_savings(&self) -> usize {
+        self.entries.iter().map(|entry| entry.savings).sum()
+    }
+
+    pub fn errors(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.error.is_some())
+            .count()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let optional = |value: Option<usize>| value.map_or("-".to_string(), |v| v.to_string());
+        let mut lines = vec![
+            "| file | original | optimized | savings | time (ms) | error |".to_string(),
+            "| --- | ---: | ---: | ---: | ---: | --- |".to_string(),
+        ];
+        for entry in &self.entries {
+            lines.push(format!(
+                "| {} | {} | {} | {} | {:.1} | {} |",
+                entry.name.replace('|', "\\|"),
+                optional(entry.original_length),
+                optional(entry.optimized_length),
+                entry.savings,
+                entry.elapsed_micros as f64 / 1000.0,
+                entry.error.as_deref().unwrap_or("").replace('|', "\\|"),
+            ));
+        }
+        lines.push(format!(
+            "| **total** | | | {} | {:.1} | {} errors |",
+            self.total_savings(),
+            self.elapsed_micros as f64 / 1000.0,
+            self.errors()
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(feature = "serde")]
+impl BatchReport {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn job(name: &str, source: &str) -> BatchJob {
+        BatchJob {
+            name: name.to_string(),
+            assembly: parser::parse_assembly(source).map_err(|errors| errors[0].to_string()),
+        }
+    }
+
+    #[test]
+    fn optimizes_every_job() {
+        let jobs = vec![
+            job("redundant", "LOAD 2\nSWAP 0, 1\nSWAP 0, 1"),
+            job("optimal", "LOAD 1"),
+            job("target", ".cells 2\n.target 0, 3"),
+            job("broken", "LOAD x"),
+        ];
+        let options = BatchOptions {
+            limits: SearchLimits {
+                max_instructions_length: Some(2),
+                max_value: Some(4),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let report = optimize_batch(&jobs, &options);
+        let summary = report
+            .entries
+            .iter()
+            .map(|entry| (entry.original_length, entry.optimized_length, entry.savings))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (Some(3), Some(1), 2),
+                (Some(1), Some(1), 0),
+                (Some(0), Some(2), 0),
+                (None, None, 0)
+            ]
+        );
+        assert_eq!(report.entries[0].program, Some(vec![Instruction::Load(2)]));
+        assert_eq!(
+            report.entries[3].error.as_deref(),
+            Some("Unknown label x at line 1:6")
+        );
+        assert_eq!(report.total_savings(), 2);
+        assert_eq!(report.errors(), 1);
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| redundant | 3 | 1 | 2 |"));
+        assert!(markdown.contains("| broken | - | - | 0 |"));
+    }
+
+    #[test]
+    fn charges_searches_that_end_within_a_chunk() {
+        let budget = Budget::new(None, Some(BUDGET_CHUNK));
+        // a single cell leaves LOAD 0, 1, 2, SWAP, XOR and INC, none of which reaches 5
+        let config = SearchConfig::new(1, 1, 3);
+        assert_eq!(search(&config, &[5], &budget), Ok(None));
+        assert_eq!(
+            search(&config, &[2], &budget),
+            Ok(Some(vec![Instruction::Load(2)]))
+        );
+        let remaining = budget.candidates.as_ref().unwrap().load(Ordering::Relaxed);
+        assert_eq!(remaining, BUDGET_CHUNK - 6 - 3);
+    }
+
+    #[test]
+    fn lists_the_programs_in_a_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in ["b.asm", "a.asm"] {
+            fs::write(directory.path().join(name), "LOAD 1").unwrap();
+        }
+        fs::create_dir(directory.path().join("nested")).unwrap();
+        assert_eq!(
+            programs_in(directory.path()).unwrap(),
+            vec![
+                directory.path().join("a.asm"),
+                directory.path().join("b.asm")
+            ]
+        );
+        assert!(programs_in(&directory.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn stops_when_the_budget_runs_out() {
+        let budget = Budget::new(None, Some(BUDGET_CHUNK));
+        assert!(budget.spend(BUDGET_CHUNK));
+        assert!(!budget.spend(1));
+        assert!(!Budget::new(Some(Duration::ZERO), None).spend(1));
+
+        let options = BatchOptions {
+            limits: SearchLimits {
+                max_instructions_length: Some(8),
+                max_value: Some(8),
+                ..Default::default()
+            },
+            candidate_budget: Some(BUDGET_CHUNK),
+            ..Default::default()
+        };
+        let jobs = vec![job("far", ".cells 3\n.target 5, 6, 7")];
+        let report = optimize_batch(&jobs, &options);
+        assert_eq!(report.entries[0].error.as_deref(), Some("budget exhausted"));
+        assert_eq!(report.entries[0].original_length, Some(0));
+    }
+}
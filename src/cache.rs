@@ -0,0 +1,303 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::SearchConfig;
+use crate::cpu::{Instruction, InstructionSet};
+
+// bumped whenever entries change format or meaning; each version keeps its own directory, so
+// older entries are never read
+pub const CACHE_VERSION: u32 = 1;
+
+// programs cost one per instruction
+pub const COST_MODEL: &str = "instructions";
+
+// where searches through `report::Backend` look for a cache when they're not given one; the
+// backend modules' own `superoptimize*` functions never consult a cache
+pub const CACHE_ENV: &str = "SUPEROPTIMUSPRIME_CACHE";
+
+// everything a search depends on but the length limit, which the cached answer accounts for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub target: Vec<usize>,
+    pub initial_state: Vec<usize>,
+    pub instruction_set: InstructionSet,
+    pub cost_model: String,
+    pub max_value: usize,
+    pub max_steps: usize,
+}
+
+impl CacheKey {
+    pub fn new(config: &SearchConfig, target_state: &[usize]) -> CacheKey {
+        CacheKey {
+            target: target_state.to_vec(),
+            initial_state: vec![0; config.max_memory_cells],
+            instruction_set: config.instruction_set.clone(),
+            cost_model: COST_MODEL.to_string(),
+            max_value: config.max_value,
+            max_steps: config.max_steps,
+        }
+    }
+
+    // the SHA-256 of the key as JSON, in hex
+    pub fn digest(&self) -> String {
+        let json = serde_json::to_vec(self).expect("keys always serialize");
+        format!("{:x}", Sha256::digest(json))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Answer {
+    // the cheapest program, whatever the length limit; only recorded by backends that always
+    // return the shortest one
+    Found(Vec<Instruction>),
+    // proof that nothing up to this many instructions reaches the target
+    NoneUpTo(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheStatistics {
+    // the backend that found the answer
+    pub backend: String,
+    pub elapsed_micros: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub version: u32,
+    // kept whole so that a digest collision reads as a miss
+    pub key: CacheKey,
+    pub answer: Answer,
+    pub statistics: CacheStatistics,
+}
+
+impl CacheEntry {
+    // what the entry says about a search up to `max_instructions_length`, if it settles it
+    pub fn answer_within(
+        &self,
+        max_instructions_length: usize,
+    ) -> Option<Option<Vec<Instruction>>> {
+        match &self.answer {
+            Answer::Found(program) if program.len() <= max_instructions_length => {
+                Some(Some(program.clone()))
+            }
+            // the cheapest program is too long
+            Answer::Found(_) => Some(None),
+            Answer::NoneUpTo(length) if max_instructions_length <= *length => Some(None),
+            Answer::NoneUpTo(_) => None,
+        }
+    }
+}
+
+// names temporary files uniquely within a process
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+// one JSON file per key, named by the key's digest. Entries are written to a temporary file
+// and renamed into place, so processes sharing the directory only ever see whole entries;
+// recording also holds a lock file next to the entry, so two writers can't interleave
+#[derive(Debug, Clone)]
+pub struct Cache {
+    directory: PathBuf,
+}
+
+impl Cache {
+    pub fn open(directory: impl AsRef<Path>) -> io::Result<Cache> {
+        let directory = directory.as_ref().join(format!("v{}", CACHE_VERSION));
+        fs::create_dir_all(&directory)?;
+        Ok(Cache { directory })
+    }
+
+    // the cache `SUPEROPTIMUSPRIME_CACHE` points at, if it's set and usable
+    pub fn from_env() -> Option<Cache> {
+        let directory = std::env::var_os(CACHE_ENV)?;
+        Cache::open(directory).ok()
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.directory.join(format!("{}.json", key.digest()))
+    }
+
+    // unreadable entries count as misses
+    pub fn get(&self, key: &CacheKey) -> Option<CacheEntry> {
+        let json = fs::read(self.path(key)).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&json).ok()?;
+        (entry.version == CACHE_VERSION && entry.key == *key).then_some(entry)
+    }
+
+    pub fn put(&self, entry: &CacheEntry) -> io::Result<()> {
+        let path = self.path(&entry.key);
+        let temporary = self.directory.join(format!(
+            ".{}.{}.{}.tmp",
+            entry.key.digest(),
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let json = serde_json::to_vec_pretty(entry).map_err(io::Error::other)?;
+        fs::write(&temporary, json)?;
+        fs::rename(&temporary, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })
+    }
+
+    // the answer to searching `config` for `target_state`, if an entry settles it
+    pub fn lookup(
+        &self,
+        config: &SearchConfig,
+        target_state: &[usize],
+    ) -> Option<Option<Vec<Instruction>>> {
+        self.get(&CacheKey::new(config, target_state))?
+            .answer_within(config.max_instructions_length)
+    }
+
+    // stores what a search within `config` found; a miss only proves there's nothing up to its
+    // length limit, and never replaces a stronger answer
+    pub fn record(
+        &self,
+        config: &SearchConfig,
+        target_state: &[usize],
+        program: &Option<Vec<Instruction>>,
+        backend: &str,
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        let key = CacheKey::new(config, target_state);
+        // held until the entry is replaced or left alone, so no other process writes in between
+        let lock = fs::File::create(self.directory.join(format!(".{}.lock", key.digest())))?;
+        lock.lock()?;
+        let answer = match program {
+            Some(program) => Answer::Found(program.clone()),
+            None => Answer::NoneUpTo(config.max_instructions_length),
+        };
+        let weaker = match (self.get(&key).map(|entry| entry.answer), &answer) {
+            (Some(Answer::Found(_)), _) => true,
+            (Some(Answer::NoneUpTo(known)), Answer::NoneUpTo(length)) => known >= *length,
+            _ => false,
+        };
+        if weaker {
+            return Ok(());
+        }
+
+        self.put(&CacheEntry {
+            version: CACHE_VERSION,
+            key,
+            answer,
+            statistics: CacheStatistics {
+                backend: backend.to_string(),
+                elapsed_micros: elapsed.as_micros() as u64,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        cache: &Cache,
+        config: &SearchConfig,
+        target: &[usize],
+        program: Option<Vec<Instruction>>,
+    ) {
+        cache
+            .record(config, target, &program, "simple", Duration::from_micros(5))
+            .unwrap();
+    }
+
+    #[test]
+    fn answers_from_what_was_recorded() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Cache::open(directory.path()).unwrap();
+        let config = SearchConfig::new(2, 2, 3);
+
+        assert_eq!(cache.lookup(&config, &[2, 1]), None);
+        record(&cache, &config, &[2, 1], None);
+        assert_eq!(cache.lookup(&config, &[2, 1]), Some(None));
+        assert_eq!(
+            cache.lookup(&SearchConfig::new(1, 2, 3), &[2, 1]),
+            Some(None)
+        );
+        // a longer search isn't settled, nor is a different space
+        assert_eq!(cache.lookup(&SearchConfig::new(3, 2, 3), &[2, 1]), None);
+        assert_eq!(cache.lookup(&SearchConfig::new(2, 2, 4), &[2, 1]), None);
+        assert_eq!(cache.lookup(&SearchConfig::new(2, 3, 3), &[2, 1]), None);
+
+        let program = vec![
+            Instruction::Load(1),
+            Instruction::Swap(0, 1),
+            Instruction::Load(2),
+        ];
+        let longer = SearchConfig::new(3, 2, 3);
+        record(&cache, &longer, &[2, 1], Some(program.clone()));
+        assert_eq!(cache.lookup(&longer, &[2, 1]), Some(Some(program.clone())));
+        assert_eq!(cache.lookup(&config, &[2, 1]), Some(None));
+        // proofs never replace programs
+        record(&cache, &config, &[2, 1], None);
+        assert_eq!(cache.lookup(&longer, &[2, 1]), Some(Some(program)));
+    }
+
+    #[test]
+    fn keeps_versions_and_keys_apart() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Cache::open(directory.path()).unwrap();
+        let config = SearchConfig::new(1, 1, 3);
+        let key = CacheKey::new(&config, &[2]);
+        assert_eq!(key.digest().len(), 64);
+        assert_ne!(
+            key.digest(),
+            CacheKey::new(&config.clone().with_max_steps(5), &[2]).digest()
+        );
+
+        record(&cache, &config, &[2], Some(vec![Instruction::Load(2)]));
+        let path = directory
+            .path()
+            .join(format!("v{}", CACHE_VERSION))
+            .join(format!("{}.json", key.digest()));
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.contains("\"found\""));
+
+        fs::write(&path, json.replacen("\"version\": 1", "\"version\": 0", 1)).unwrap();
+        assert_eq!(cache.lookup(&config, &[2]), None);
+        fs::write(&path, "{").unwrap();
+        assert_eq!(cache.lookup(&config, &[2]), None);
+    }
+
+    #[test]
+    fn survives_concurrent_writers() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = SearchConfig::new(2, 2, 3);
+        let program = vec![Instruction::Load(5), Instruction::Inc(0)];
+        std::thread::scope(|scope| {
+            for writer in 0..8 {
+                let (directory, config, program) = (&directory, &config, &program);
+                scope.spawn(move || {
+                    let cache = Cache::open(directory.path()).unwrap();
+                    for _ in 0..20 {
+                        record(&cache, config, &[2, 0], Some(vec![Instruction::Load(2)]));
+                        assert!(cache.lookup(config, &[2, 0]).is_some());
+                        // misses racing the program must never replace it
+                        match writer % 2 {
+                            0 => record(&cache, config, &[6, 0], Some(program.clone())),
+                            _ => record(&cache, &SearchConfig::new(1, 2, 3), &[6, 0], None),
+                        }
+                    }
+                });
+            }
+        });
+        let cache = Cache::open(directory.path()).unwrap();
+        assert_eq!(cache.lookup(&config, &[6, 0]), Some(Some(program)));
+        let entries = fs::read_dir(directory.path().join(format!("v{}", CACHE_VERSION)))
+            .unwrap()
+            .filter(|file| {
+                let name = file.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(".json")
+            })
+            .count();
+        assert_eq!(entries, 2);
+    }
+}
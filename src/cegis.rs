@@ -0,0 +1,107 @@
+use crate::config::SearchConfig;
+use crate::cpu::Instruction;
+use crate::superoptimizer::generate_and_search_programs;
+use crate::symbolic::{self, Equivalence};
+use crate::verify::{self, Outcome, VerifyError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Synthesis {
+    // equivalent to the specification on every input over the domain
+    pub program: Option<Vec<Instruction>>,
+    // candidates found, including the accepted one
+    pub iterations: usize,
+    // the inputs candidates were tested on: the all-zero state first, then a counterexample to
+    // each rejected candidate
+    pub tests: Vec<Vec<usize>>,
+}
+
+// finds the shortest program within `config` that behaves like `spec` on every initial state
+// whose cells hold values from `domain`: candidates only have to pass a growing set of test
+// inputs, and each one that fails the full check adds the input it failed on
+pub fn synthesize(
+    spec: &[Instruction],
+    config: &SearchConfig,
+    domain: &[usize],
+) -> Result<Synthesis, VerifyError> {
+    let cells = config.max_memory_cells;
+    // refuse up front rather than after searching for a first candidate
+    verify::input_count(cells, domain)?;
+    let mut tests = vec![vec![0; cells]];
+    let mut expected: Vec<Outcome> = vec![verify::run(spec, &tests[0], config.max_steps)];
+    let mut iterations = 0;
+
+    loop {
+        let tester = |candidate: &Vec<Instruction>| {
+            tests.iter().zip(&expected).all(|(input, expected)| {
+                verify::same(&verify::run(candidate, input, config.max_steps), expected)
+            })
+        };
+        let candidate = match generate_and_search_programs(config, tester) {
+            Some(candidate) => candidate,
+            None => {
+                return Ok(Synthesis {
+                    program: None,
+                    iterations,
+                    tests,
+                })
+            }
+        };
+        iterations += 1;
+
+        // a symbolic proof covers every input at once; otherwise check the whole domain
+        let proven = symbolic::equivalent(spec, &candidate, cells, config.max_steps)
+            == Ok(Equivalence::Proven);
+        let checked = match proven {
+            true => Ok(()),
+            false => verify::equivalent_within(spec, &candidate, cells, domain, config.max_steps),
+        };
+        match checked {
+            Ok(()) => {
+                return Ok(Synthesis {
+                    program: Some(candidate),
+                    iterations,
+                    tests,
+                })
+            }
+            Err(VerifyError::Counterexample(counterexample)) => {
+                expected.push(counterexample.left);
+                tests.push(counterexample.input);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::InstructionSet;
+    use crate::parser::parse;
+
+    #[test]
+    fn refines_candidates_with_counterexamples() {
+        // a roundabout `ADD 0, 1`, which the zero input alone can't tell from doing nothing
+        let spec = parse("ADD 0, 1\nADD 0, 1\nSUB 0, 1").unwrap();
+        let config = SearchConfig::new(2, 2, 1)
+            .with_instruction_set(InstructionSet::new(&["ADD", "SUB", "INC"]));
+        let synthesis = synthesize(&spec, &config, &[0, 1, 2, 3]).unwrap();
+
+        assert_eq!(synthesis.program, Some(vec![Instruction::Add(0, 1)]));
+        assert!(synthesis.iterations > 1);
+        assert_eq!(synthesis.tests.len(), synthesis.iterations);
+    }
+
+    #[test]
+    fn gives_up_when_nothing_fits() {
+        let spec = parse("INC 0\nINC 0").unwrap();
+        let config = SearchConfig::new(1, 1, 1);
+        let synthesis = synthesize(&spec, &config, &[0, 1]).unwrap();
+        assert_eq!(synthesis.program, None);
+
+        let config = SearchConfig::new(1, 64, 1);
+        assert!(matches!(
+            synthesize(&spec, &config, &[0, 1]),
+            Err(VerifyError::TooManyInputs { cells: 64, .. })
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cpu::{Instruction, InstructionSet};
+use crate::isa::InstructionSemantics;
+
+// candidates still running after this many executed instructions are rejected
+pub const DEFAULT_MAX_STEPS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "I::Alphabet: serde::Serialize",
+        deserialize = "I::Alphabet: serde::Deserialize<'de>"
+    ))
+)]
+pub struct SearchConfig<I: InstructionSemantics = Instruction> {
+    pub max_instructions_length: usize,
+    pub max_memory_cells: usize,
+    pub max_value: usize,
+    pub max_steps: usize,
+    pub instruction_set: I::Alphabet,
+}
+
+impl SearchConfig {
+    pub fn new(
+        max_instructions_length: usize,
+        max_memory_cells: usize,
+        max_value: usize,
+    ) -> SearchConfig {
+        SearchConfig::for_instruction_set(
+            max_instructions_length,
+            max_memory_cells,
+            max_value,
+            InstructionSet::default(),
+        )
+    }
+}
+
+// the most values `SearchConfig::covering` tries for immediates: one past a large immediate like
+// `LOAD 100000000` would put every value below it in the alphabet
+pub const MAX_COVERED_VALUE: usize = 256;
+
+// replaces parts of the search space `SearchConfig::covering` derives from a program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchLimits {
+    pub max_instructions_length: Option<usize>,
+    pub max_value: Option<usize>,
+    pub instruction_set: Option<InstructionSet>,
+    pub max_steps: Option<usize>,
+}
+
+impl SearchConfig {
+    // the smallest search space still holding `program`: no more instructions than it has,
+    // the operations it uses and values up to its largest immediate (by magnitude if `signed`),
+    // but no more than `MAX_COVERED_VALUE` of them
+    pub fn covering(program: &[Instruction], cells: usize, signed: bool) -> SearchConfig {
+        let largest_immediate = program
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Load(value) | Instruction::LoadTo(_, value) => Some(*value),
+                _ => None,
+            })
+            .map(|value| match signed {
+                true => (value as isize).unsigned_abs(),
+                false => value,
+            })
+            .max();
+
+        let mut operations = Vec::new();
+        for operation in program.iter().map(Instruction::operation) {
+            if !operations.contains(&operation) {
+                operations.push(operation);
+            }
+        }
+        let mut instruction_set = match operations.is_empty() {
+            true => InstructionSet::default(),
+            false => {
+                InstructionSet::new(&operations.iter().map(String::as_str).collect::<Vec<_>>())
+            }
+        };
+        if signed {
+            instruction_set = instruction_set.signed();
+        }
+
+        SearchConfig::new(
+            program.len().max(1),
+            cells,
+            largest_immediate.map_or(1, |value| value.saturating_add(1).min(MAX_COVERED_VALUE)),
+        )
+        .with_instruction_set(instruction_set)
+    }
+
+    pub fn limited(mut self, limits: &SearchLimits) -> SearchConfig {
+        if let Some(max_instructions_length) = limits.max_instructions_length {
+            self.max_instructions_length = max_instructions_length;
+        }
+        if let Some(max_value) = limits.max_value {
+            self.max_value = max_value;
+        }
+        if let Some(instruction_set) = &limits.instruction_set {
+            self.instruction_set = instruction_set.clone();
+        }
+        if let Some(max_steps) = limits.max_steps {
+            self.max_steps = max_steps;
+        }
+        self
+    }
+}
+
+impl<I: InstructionSemantics> SearchConfig<I> {
+    pub fn for_instruction_set(
+        max_instructions_length: usize,
+        max_memory_cells: usize,
+        max_value: usize,
+        instruction_set: I::Alphabet,
+    ) -> SearchConfig<I> {
+        SearchConfig {
+            max_instructions_length,
+            max_memory_cells,
+            max_value,
+            max_steps: DEFAULT_MAX_STEPS,
+            instruction_set,
+        }
+    }
+
+    pub fn with_instruction_set(mut self, instruction_set: I::Alphabet) -> SearchConfig<I> {
+        self.instruction_set = instruction_set;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> SearchConfig<I> {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn instructions(&self, program_length: usize) -> Vec<I> {
+        I::enumerate(
+            &self.instruction_set,
+            self.max_memory_cells,
+            self.max_value,
+            program_length,
+        )
+    }
+}
@@ -1,13 +1,34 @@
-use strum::EnumIter;
+use std::fmt;
 
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::isa::InstructionSemantics;
+use crate::iters::product;
 use crate::operations::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, EnumIter)]
+// serialized as `{"LOAD": 3}`, `{"SWAP": [0, 1]}`, ... keyed by mnemonic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, EnumIter)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
 pub enum Instruction {
     Load(usize),
     Swap(usize, usize),
     Xor(usize, usize),
     Inc(usize),
+    Add(usize, usize),
+    Sub(usize, usize),
+    And(usize, usize),
+    Or(usize, usize),
+    Not(usize),
+    Dec(usize),
+    Shl(usize, usize),
+    Shr(usize, usize),
+    Mov(usize, usize),
+    Neg(usize),
+    Jmp(usize),
+    Jz(usize, usize),
+    Jnz(usize, usize),
+    LoadTo(usize, usize),
 }
 
 impl Instruction {
@@ -17,50 +38,420 @@ impl Instruction {
             Instruction::Swap(_, _) => String::from("SWAP"),
             Instruction::Xor(_, _) => String::from("XOR"),
             Instruction::Inc(_) => String::from("INC"),
+            Instruction::Add(_, _) => String::from("ADD"),
+            Instruction::Sub(_, _) => String::from("SUB"),
+            Instruction::And(_, _) => String::from("AND"),
+            Instruction::Or(_, _) => String::from("OR"),
+            Instruction::Not(_) => String::from("NOT"),
+            Instruction::Dec(_) => String::from("DEC"),
+            Instruction::Shl(_, _) => String::from("SHL"),
+            Instruction::Shr(_, _) => String::from("SHR"),
+            Instruction::Mov(_, _) => String::from("MOV"),
+            Instruction::Neg(_) => String::from("NEG"),
+            Instruction::Jmp(_) => String::from("JMP"),
+            Instruction::Jz(_, _) => String::from("JZ"),
+            Instruction::Jnz(_, _) => String::from("JNZ"),
+            Instruction::LoadTo(_, _) => String::from("LOADTO"),
         }
     }
 
     pub fn arguments(&self) -> Vec<usize> {
         match self {
             Instruction::Load(value) => vec![*value],
-            Instruction::Swap(memory1, memory2) => vec![*memory1, *memory2],
-            Instruction::Xor(memory1, memory2) => vec![*memory1, *memory2],
-            Instruction::Inc(memory) => vec![*memory],
+            Instruction::Swap(memory1, memory2)
+            | Instruction::Xor(memory1, memory2)
+            | Instruction::Add(memory1, memory2)
+            | Instruction::Sub(memory1, memory2)
+            | Instruction::And(memory1, memory2)
+            | Instruction::Or(memory1, memory2)
+            | Instruction::Shl(memory1, memory2)
+            | Instruction::Shr(memory1, memory2)
+            | Instruction::Mov(memory1, memory2) => vec![*memory1, *memory2],
+            Instruction::Inc(memory)
+            | Instruction::Not(memory)
+            | Instruction::Dec(memory)
+            | Instruction::Neg(memory) => vec![*memory],
+            Instruction::Jmp(target) => vec![*target],
+            Instruction::Jz(memory, target) | Instruction::Jnz(memory, target) => {
+                vec![*memory, *target]
+            }
+            Instruction::LoadTo(memory, value) => vec![*memory, *value],
+        }
+    }
+
+    pub fn jump_target(&self) -> Option<usize> {
+        match self {
+            Instruction::Jmp(target) | Instruction::Jz(_, target) | Instruction::Jnz(_, target) => {
+                Some(*target)
+            }
+            _ => None,
+        }
+    }
+}
+
+// the operations a search is allowed to use, by mnemonic
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstructionSet {
+    operations: Vec<String>,
+    signed: bool,
+}
+
+impl InstructionSet {
+    pub fn new(operations: &[&str]) -> InstructionSet {
+        InstructionSet {
+            operations: operations.iter().map(|op| op.to_string()).collect(),
+            signed: false,
+        }
+    }
+
+    pub fn all() -> InstructionSet {
+        InstructionSet {
+            operations: Instruction::iter()
+                .map(|instruction| instruction.operation())
+                .collect(),
+            signed: false,
+        }
+    }
+
+    // immediates range over -(max_value - 1)..max_value in two's complement instead of
+    // 0..max_value
+    pub fn signed(mut self) -> InstructionSet {
+        self.signed = true;
+        self
+    }
+
+    pub fn with(mut self, operation: &str) -> InstructionSet {
+        if !self.contains(operation) {
+            self.operations.push(operation.to_string());
+        }
+        self
+    }
+
+    pub fn without(mut self, operation: &str) -> InstructionSet {
+        self.operations.retain(|op| op != operation);
+        self
+    }
+
+    pub fn contains(&self, operation: &str) -> bool {
+        self.operations.iter().any(|op| op == operation)
+    }
+
+    // every concrete instruction of the enabled operations, in `Instruction` declaration order;
+    // jumps may target any instruction of a `program_length` long program or its end
+    pub fn instructions(
+        &self,
+        max_memory_cells: usize,
+        max_value: usize,
+        program_length: usize,
+    ) -> Vec<Instruction> {
+        let cells = (0..max_memory_cells).collect::<Vec<_>>();
+        let pairs = product(&cells, 2);
+        let values = match self.signed {
+            true => (1 - max_value as isize..max_value as isize)
+                .map(|value| value as usize)
+                .collect::<Vec<_>>(),
+            false => (0..max_value).collect::<Vec<_>>(),
+        };
+        let targets = (0..=program_length).collect::<Vec<_>>();
+        let loads = cells
+            .iter()
+            .flat_map(|cell| values.iter().map(move |value| (*cell, *value)))
+            .collect::<Vec<_>>();
+        let branches = cells
+            .iter()
+            .flat_map(|cell| targets.iter().map(move |target| (*cell, *target)))
+            .collect::<Vec<_>>();
+
+        Instruction::iter()
+            .map(|instruction| instruction.operation())
+            .filter(|operation| self.contains(operation))
+            .flat_map(|operation| match operation.as_str() {
+                "LOAD" => values
+                    .iter()
+                    .map(|v| Instruction::Load(*v))
+                    .collect::<Vec<_>>(),
+                "SWAP" => pairs
+                    .iter()
+                    .map(|c| Instruction::Swap(c[0], c[1]))
+                    .collect(),
+                "XOR" => pairs.iter().map(|c| Instruction::Xor(c[0], c[1])).collect(),
+                "INC" => cells.iter().map(|c| Instruction::Inc(*c)).collect(),
+                "ADD" => pairs.iter().map(|c| Instruction::Add(c[0], c[1])).collect(),
+                "SUB" => pairs.iter().map(|c| Instruction::Sub(c[0], c[1])).collect(),
+                "AND" => pairs.iter().map(|c| Instruction::And(c[0], c[1])).collect(),
+                "OR" => pairs.iter().map(|c| Instruction::Or(c[0], c[1])).collect(),
+                "NOT" => cells.iter().map(|c| Instruction::Not(*c)).collect(),
+                "DEC" => cells.iter().map(|c| Instruction::Dec(*c)).collect(),
+                "SHL" => pairs.iter().map(|c| Instruction::Shl(c[0], c[1])).collect(),
+                "SHR" => pairs.iter().map(|c| Instruction::Shr(c[0], c[1])).collect(),
+                "MOV" => pairs.iter().map(|c| Instruction::Mov(c[0], c[1])).collect(),
+                "NEG" => cells.iter().map(|c| Instruction::Neg(*c)).collect(),
+                "JMP" => targets.iter().map(|t| Instruction::Jmp(*t)).collect(),
+                "JZ" => branches
+                    .iter()
+                    .map(|&(c, t)| Instruction::Jz(c, t))
+                    .collect(),
+                "JNZ" => branches
+                    .iter()
+                    .map(|&(c, t)| Instruction::Jnz(c, t))
+                    .collect(),
+                "LOADTO" => loads
+                    .iter()
+                    .map(|&(c, v)| Instruction::LoadTo(c, v))
+                    .collect(),
+                _ => panic!("Unknown operation: {}", operation),
+            })
+            .collect()
+    }
+}
+
+impl Default for InstructionSet {
+    fn default() -> InstructionSet {
+        InstructionSet::new(&["LOAD", "SWAP", "XOR", "INC"])
+    }
+}
+
+// reinterprets cell values as two's complement signed numbers
+pub fn to_signed(state: &[usize]) -> Vec<isize> {
+    state.iter().map(|value| *value as isize).collect()
+}
+
+pub fn from_signed(values: &[isize]) -> Vec<usize> {
+    values.iter().map(|value| *value as usize).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecError {
+    CellOutOfBounds { instruction: usize, cell: usize },
+    JumpOutOfBounds { instruction: usize, target: usize },
+    StepLimitExceeded { steps: usize },
+    // stepping a program that has already run past its last instruction
+    Halted { program_counter: usize },
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::CellOutOfBounds { instruction, cell } => write!(
+                f,
+                "Instruction {} accesses out of bounds cell {}",
+                instruction, cell
+            ),
+            ExecError::JumpOutOfBounds {
+                instruction,
+                target,
+            } => write!(
+                f,
+                "Instruction {} jumps to out of bounds target {}",
+                instruction, target
+            ),
+            ExecError::StepLimitExceeded { steps } => {
+                write!(f, "Program did not halt within {} steps", steps)
+            }
+            ExecError::Halted { program_counter } => write!(
+                f,
+                "Program already halted: {} is past its last instruction",
+                program_counter
+            ),
         }
     }
 }
 
+impl std::error::Error for ExecError {}
+
+impl InstructionSemantics for Instruction {
+    type Alphabet = InstructionSet;
+
+    fn enumerate(
+        alphabet: &InstructionSet,
+        max_memory_cells: usize,
+        max_value: usize,
+        program_length: usize,
+    ) -> Vec<Instruction> {
+        alphabet.instructions(max_memory_cells, max_value, program_length)
+    }
+
+    fn execute(&self, state: &mut [usize], program_counter: usize) -> usize {
+        match *self {
+            Instruction::Load(value) => load(state, value),
+            Instruction::Swap(memory1, memory2) => swap(state, memory1, memory2),
+            Instruction::Xor(memory1, memory2) => xor(state, memory1, memory2),
+            Instruction::Inc(memory) => inc(state, memory),
+            Instruction::Add(memory1, memory2) => add(state, memory1, memory2),
+            Instruction::Sub(memory1, memory2) => sub(state, memory1, memory2),
+            Instruction::And(memory1, memory2) => and(state, memory1, memory2),
+            Instruction::Or(memory1, memory2) => or(state, memory1, memory2),
+            Instruction::Not(memory) => not(state, memory),
+            Instruction::Dec(memory) => dec(state, memory),
+            Instruction::Shl(memory1, memory2) => shl(state, memory1, memory2),
+            Instruction::Shr(memory1, memory2) => shr(state, memory1, memory2),
+            Instruction::Mov(memory1, memory2) => mov(state, memory1, memory2),
+            Instruction::Neg(memory) => neg(state, memory),
+            Instruction::LoadTo(memory, value) => load_to(state, memory, value),
+            Instruction::Jmp(target) => return target,
+            Instruction::Jz(memory, target) if state[memory] == 0 => return target,
+            Instruction::Jnz(memory, target) if state[memory] != 0 => return target,
+            Instruction::Jz(_, _) | Instruction::Jnz(_, _) => {}
+        }
+        program_counter + 1
+    }
+
+    fn reads(&self) -> Vec<usize> {
+        match *self {
+            Instruction::Load(_) | Instruction::LoadTo(_, _) | Instruction::Jmp(_) => vec![],
+            Instruction::Mov(_, memory2) => vec![memory2],
+            Instruction::Jz(memory, _) | Instruction::Jnz(memory, _) => vec![memory],
+            _ => self.arguments(),
+        }
+    }
+
+    fn writes(&self) -> Vec<usize> {
+        match *self {
+            Instruction::Load(_) => vec![0],
+            Instruction::Swap(memory1, memory2) => vec![memory1, memory2],
+            Instruction::Jmp(_) | Instruction::Jz(_, _) | Instruction::Jnz(_, _) => vec![],
+            _ => vec![self.arguments()[0]],
+        }
+    }
+
+    fn jump_target(&self) -> Option<usize> {
+        Instruction::jump_target(self)
+    }
+
+    fn falls_through(&self) -> bool {
+        !matches!(self, Instruction::Jmp(_))
+    }
+}
+
+fn check_cells<I: InstructionSemantics>(
+    index: usize,
+    instruction: &I,
+    cells: usize,
+) -> Result<(), ExecError> {
+    let mut accessed = instruction.reads().into_iter().chain(instruction.writes());
+    match accessed.find(|cell| *cell >= cells) {
+        Some(cell) => Err(ExecError::CellOutOfBounds {
+            instruction: index,
+            cell,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn check_target(index: usize, target: usize, program_length: usize) -> Result<(), ExecError> {
+    if target > program_length {
+        return Err(ExecError::JumpOutOfBounds {
+            instruction: index,
+            target,
+        });
+    }
+    Ok(())
+}
+
+// checks that every instruction only names cells that exist on a CPU with `cells` cells and
+// only jumps inside the program (jumping right past its last instruction halts)
+pub fn validate<I: InstructionSemantics>(program: &[I], cells: usize) -> Result<(), ExecError> {
+    (0..program.len()).try_for_each(|index| validate_instruction(program, index, cells))
+}
+
+// what `validate` checks, for the instruction at `index` alone
+pub fn validate_instruction<I: InstructionSemantics>(
+    program: &[I],
+    index: usize,
+    cells: usize,
+) -> Result<(), ExecError> {
+    let instruction = &program[index];
+    check_cells(index, instruction, cells)?;
+    match instruction.jump_target() {
+        Some(target) => check_target(index, target, program.len()),
+        None => Ok(()),
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CPU {
     pub state: Vec<usize>,
+    pub max_steps: Option<usize>,
 }
 
 impl CPU {
     pub fn new(max_allowed_memory_cells: usize) -> CPU {
         CPU {
             state: vec![0; max_allowed_memory_cells],
+            max_steps: None,
         }
     }
 
+    // bounds how many instructions a single run may execute, so loops can't run forever
+    pub fn with_max_steps(mut self, max_steps: usize) -> CPU {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
     pub fn reset(&mut self) {
         self.state = vec![0; self.state.len()];
     }
 
-    pub fn execute(&mut self, program: &Vec<Instruction>) {
-        for instruction in program {
-            match *instruction {
-                Instruction::Load(value) => load(&mut self.state, value),
-                Instruction::Swap(memory1, memory2) => swap(&mut self.state, memory1, memory2),
-                Instruction::Xor(memory1, memory2) => xor(&mut self.state, memory1, memory2),
-                Instruction::Inc(memory) => inc(&mut self.state, memory),
+    pub fn execute<I: InstructionSemantics>(&mut self, program: &[I]) {
+        if let Err(error) = self.try_execute(program) {
+            panic!("{}", error);
+        }
+    }
+
+    // like `execute`, but stops at the first faulting instruction instead of panicking;
+    // the state keeps the effects of the instructions that ran before it
+    pub fn try_execute<I: InstructionSemantics>(&mut self, program: &[I]) -> Result<(), ExecError> {
+        let mut program_counter = 0;
+        let mut steps = 0;
+
+        // cell operands are fixed, so each instruction is bounds checked once rather than on
+        // every step; errors are still only reported once the instruction is reached
+        let checks = program
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| check_cells(index, instruction, self.state.len()))
+            .collect::<Vec<_>>();
+
+        while let Some(instruction) = program.get(program_counter) {
+            if self.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+                return Err(ExecError::StepLimitExceeded { steps });
             }
+            checks[program_counter].clone()?;
+
+            let next = instruction.execute(&mut self.state, program_counter);
+            check_target(program_counter, next, program.len())?;
+
+            program_counter = next;
+            steps += 1;
+        }
+        Ok(())
+    }
+
+    // executes only the instruction at `program_counter` and returns the next program counter;
+    // a faulting instruction leaves the state untouched
+    pub fn step<I: InstructionSemantics>(
+        &mut self,
+        program: &[I],
+        program_counter: usize,
+    ) -> Result<usize, ExecError> {
+        let instruction = program
+            .get(program_counter)
+            .ok_or(ExecError::Halted { program_counter })?;
+        check_cells(program_counter, instruction, self.state.len())?;
+        if let Some(target) = instruction.jump_target() {
+            check_target(program_counter, target, program.len())?;
         }
+        Ok(instruction.execute(&mut self.state, program_counter))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::strategies;
+    use proptest::prelude::*;
 
     #[test]
     fn can_execute_program() {
@@ -76,4 +467,284 @@ mod tests {
         cpu.execute(&program);
         assert_eq!(cpu.state, vec![0, 0, 0, 0, 3, 0]);
     }
+
+    #[test]
+    fn try_execute_reports_out_of_bounds_cell() {
+        let program = vec![
+            Instruction::Load(3),
+            Instruction::Swap(0, 9),
+            Instruction::Inc(1),
+        ];
+        let mut cpu = CPU::new(6);
+        let result = cpu.try_execute(&program);
+        assert_eq!(
+            result,
+            Err(ExecError::CellOutOfBounds {
+                instruction: 1,
+                cell: 9
+            })
+        );
+        assert_eq!(cpu.state, vec![3, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn try_execute_runs_valid_program() {
+        let program = vec![Instruction::Load(3), Instruction::Swap(0, 5)];
+        let mut cpu = CPU::new(6);
+        assert!(cpu.try_execute(&program).is_ok());
+        assert_eq!(cpu.state, vec![0, 0, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn can_validate() {
+        let program = vec![Instruction::Inc(2), Instruction::Xor(1, 4)];
+        assert!(validate(&program, 5).is_ok());
+        assert_eq!(
+            validate(&program, 4),
+            Err(ExecError::CellOutOfBounds {
+                instruction: 1,
+                cell: 4
+            })
+        );
+        assert_eq!(
+            validate(&[Instruction::Load(1)], 0),
+            Err(ExecError::CellOutOfBounds {
+                instruction: 0,
+                cell: 0
+            })
+        );
+    }
+
+    #[test]
+    fn can_execute_arithmetic_program() {
+        let program = vec![
+            Instruction::Load(6),
+            Instruction::Mov(1, 0),
+            Instruction::Load(3),
+            Instruction::Add(1, 0),
+            Instruction::Sub(1, 0),
+            Instruction::Shl(1, 0),
+            Instruction::Dec(0),
+            Instruction::Shr(1, 0),
+            Instruction::Mov(2, 1),
+            Instruction::And(2, 0),
+            Instruction::Or(2, 0),
+            Instruction::Not(0),
+            Instruction::Neg(0),
+        ];
+        let mut cpu = CPU::new(3);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, vec![3, 12, 2]);
+    }
+
+    #[test]
+    fn default_instruction_set_keeps_original_alphabet() {
+        let instructions = InstructionSet::default().instructions(2, 2, 3);
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::Load(0),
+                Instruction::Load(1),
+                Instruction::Swap(0, 0),
+                Instruction::Swap(1, 0),
+                Instruction::Swap(0, 1),
+                Instruction::Swap(1, 1),
+                Instruction::Xor(0, 0),
+                Instruction::Xor(1, 0),
+                Instruction::Xor(0, 1),
+                Instruction::Xor(1, 1),
+                Instruction::Inc(0),
+                Instruction::Inc(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_toggle_operations() {
+        let set = InstructionSet::default().without("XOR").with("NEG");
+        let instructions = set.instructions(2, 1, 3);
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::Load(0),
+                Instruction::Swap(0, 0),
+                Instruction::Swap(1, 0),
+                Instruction::Swap(0, 1),
+                Instruction::Swap(1, 1),
+                Instruction::Inc(0),
+                Instruction::Inc(1),
+                Instruction::Neg(0),
+                Instruction::Neg(1),
+            ]
+        );
+        assert_eq!(InstructionSet::all().instructions(1, 1, 0).len(), 18);
+    }
+
+    #[test]
+    fn can_execute_loop() {
+        // cell 1 += 2 * cell 0
+        let program = vec![
+            Instruction::Load(3),
+            Instruction::Jz(0, 5),
+            Instruction::Inc(1),
+            Instruction::Inc(1),
+            Instruction::Dec(0),
+            Instruction::Jnz(0, 2),
+            Instruction::Jmp(8),
+            Instruction::Inc(1),
+        ];
+        let mut cpu = CPU::new(2);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, vec![0, 6]);
+    }
+
+    #[test]
+    fn try_execute_stops_at_step_limit() {
+        let program = vec![Instruction::Inc(0), Instruction::Jmp(0)];
+        let mut cpu = CPU::new(1).with_max_steps(9);
+        assert_eq!(
+            cpu.try_execute(&program),
+            Err(ExecError::StepLimitExceeded { steps: 9 })
+        );
+        assert_eq!(cpu.state, vec![5]);
+    }
+
+    #[test]
+    fn stepping_a_halted_program_fails() {
+        let program = vec![Instruction::Inc(0)];
+        let mut cpu = CPU::new(1);
+        assert_eq!(cpu.step(&program, 0), Ok(1));
+        for program_counter in [1, 5] {
+            assert_eq!(
+                cpu.step(&program, program_counter),
+                Err(ExecError::Halted { program_counter })
+            );
+        }
+        assert_eq!(
+            cpu.step::<Instruction>(&[], 0),
+            Err(ExecError::Halted { program_counter: 0 })
+        );
+        assert_eq!(cpu.state, vec![1]);
+    }
+
+    #[test]
+    fn validate_rejects_jump_out_of_program() {
+        let program = vec![Instruction::Jnz(0, 2), Instruction::Jmp(3)];
+        assert_eq!(
+            validate(&program, 1),
+            Err(ExecError::JumpOutOfBounds {
+                instruction: 1,
+                target: 3
+            })
+        );
+        assert_eq!(
+            CPU::new(1).try_execute(&program),
+            Err(ExecError::JumpOutOfBounds {
+                instruction: 1,
+                target: 3
+            })
+        );
+    }
+
+    #[test]
+    fn can_load_into_any_cell() {
+        let program = vec![
+            Instruction::LoadTo(2, 7),
+            Instruction::Load(1),
+            Instruction::LoadTo(0, 4),
+        ];
+        let mut cpu = CPU::new(3);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, vec![4, 0, 7]);
+        assert_eq!(
+            validate(&[Instruction::LoadTo(3, 0)], 3),
+            Err(ExecError::CellOutOfBounds {
+                instruction: 0,
+                cell: 3
+            })
+        );
+    }
+
+    #[test]
+    fn can_report_reads_and_writes() {
+        assert_eq!(Instruction::Load(3).reads(), Vec::<usize>::new());
+        assert_eq!(Instruction::Load(3).writes(), vec![0]);
+        assert_eq!(Instruction::Swap(1, 2).reads(), vec![1, 2]);
+        assert_eq!(Instruction::Swap(1, 2).writes(), vec![1, 2]);
+        assert_eq!(Instruction::Xor(1, 2).reads(), vec![1, 2]);
+        assert_eq!(Instruction::Xor(1, 2).writes(), vec![1]);
+        assert_eq!(Instruction::Mov(1, 2).reads(), vec![2]);
+        assert_eq!(Instruction::Jnz(4, 0).reads(), vec![4]);
+        assert_eq!(Instruction::Jnz(4, 0).writes(), Vec::<usize>::new());
+        assert_eq!(Instruction::LoadTo(5, 1).writes(), vec![5]);
+    }
+
+    #[test]
+    fn can_enumerate_signed_immediates() {
+        let set = InstructionSet::new(&["LOAD"]).signed();
+        assert_eq!(
+            to_signed(
+                &set.instructions(1, 3, 1)
+                    .iter()
+                    .map(|op| op.arguments()[0])
+                    .collect::<Vec<_>>()
+            ),
+            vec![-2, -1, 0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn can_run_signed_program() {
+        let program = vec![
+            Instruction::Load(from_signed(&[-5])[0]),
+            Instruction::Mov(1, 0),
+            Instruction::LoadTo(2, 3),
+            Instruction::Add(1, 2),
+            Instruction::Neg(2),
+        ];
+        let mut cpu = CPU::new(3);
+        cpu.execute(&program);
+        assert_eq!(to_signed(&cpu.state), vec![-5, -2, -3]);
+    }
+
+    fn run(program: &[Instruction], input: &[usize]) -> Vec<usize> {
+        let mut cpu = CPU::new(input.len());
+        cpu.state.copy_from_slice(input);
+        cpu.execute(program);
+        cpu.state
+    }
+
+    // `program` with `pair` run twice just before its `at`th instruction
+    fn twice(program: &[Instruction], at: usize, pair: Instruction) -> Vec<Instruction> {
+        let mut program = program.to_vec();
+        let at = at % (program.len() + 1);
+        program.splice(at..at, [pair, pair]);
+        program
+    }
+
+    proptest! {
+        #[test]
+        fn swapping_twice_changes_nothing(
+            program in strategies::straight_line_program(4, 8),
+            input in strategies::state(4),
+            at: usize,
+            a in 0..4usize,
+            b in 0..4usize,
+        ) {
+            let swapped = twice(&program, at, Instruction::Swap(a, b));
+            prop_assert_eq!(run(&swapped, &input), run(&program, &input));
+        }
+
+        // XOR a, a clears the cell instead
+        #[test]
+        fn xoring_twice_restores_the_state(
+            program in strategies::straight_line_program(4, 8),
+            input in strategies::state(4),
+            at: usize,
+            (a, b) in (0..4usize, 0..4usize).prop_filter("distinct cells", |(a, b)| a != b),
+        ) {
+            let xored = twice(&program, at, Instruction::Xor(a, b));
+            prop_assert_eq!(run(&xored, &input), run(&program, &input));
+        }
+    }
 }
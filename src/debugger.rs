@@ -0,0 +1,422 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::cpu::{validate_instruction, ExecError, Instruction, CPU};
+use crate::isa::InstructionSemantics;
+
+// stops execution once `cell` changes, or only once it changes to `value`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    pub cell: usize,
+    pub value: Option<usize>,
+}
+
+// why the debugger gave control back
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Stepped,
+    Halted,
+    Breakpoint(usize),
+    Watchpoint(Watchpoint, usize),
+    Fault(ExecError),
+    StepLimit(usize),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Stepped => write!(f, "stepped"),
+            Event::Halted => write!(f, "halted"),
+            Event::Breakpoint(index) => write!(f, "breakpoint at {}", index),
+            Event::Watchpoint(watchpoint, value) => {
+                write!(f, "watchpoint: cell {} is now {}", watchpoint.cell, value)
+            }
+            Event::Fault(error) => write!(f, "fault: {}", error),
+            Event::StepLimit(steps) => write!(f, "stopped after {} steps", steps),
+        }
+    }
+}
+
+pub struct Debugger<I: InstructionSemantics = Instruction> {
+    program: Vec<I>,
+    cpu: CPU,
+    program_counter: usize,
+    // program counter and state before every executed step, oldest first
+    history: Vec<(usize, Vec<usize>)>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<I: InstructionSemantics> Debugger<I> {
+    pub fn new(program: Vec<I>, cells: usize) -> Debugger<I> {
+        Debugger {
+            program,
+            cpu: CPU::new(cells),
+            program_counter: 0,
+            history: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn program(&self) -> &[I] {
+        &self.program
+    }
+
+    pub fn state(&self) -> &[usize] {
+        &self.cpu.state
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    // executed instructions since the start, not counting the ones stepped back over
+    pub fn steps(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn halted(&self) -> bool {
+        self.program_counter >= self.program.len()
+    }
+
+    pub fn current(&self) -> Option<&I> {
+        self.program.get(self.program_counter)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_breakpoint(&mut self, index: usize) {
+        self.breakpoints.insert(index);
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        self.breakpoints.remove(&index)
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    // removes every watchpoint on `cell`
+    pub fn unwatch(&mut self, cell: usize) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.cell != cell);
+        self.watchpoints.len() != before
+    }
+
+    // appends `instruction` to the program, unless it names a cell the CPU doesn't have or jumps
+    // past the new end
+    pub fn push(&mut self, instruction: I) -> Result<(), ExecError> {
+        self.program.push(instruction);
+        let index = self.program.len() - 1;
+        validate_instruction(&self.program, index, self.cpu.state.len()).inspect_err(|_| {
+            self.program.pop();
+        })
+    }
+
+    // back to the first instruction and a zeroed state, keeping break- and watchpoints
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.program_counter = 0;
+        self.history.clear();
+    }
+
+    pub fn step(&mut self) -> Event {
+        if self.halted() {
+            return Event::Halted;
+        }
+        let before = self.cpu.state.clone();
+        match self.cpu.step(&self.program, self.program_counter) {
+            Ok(next) => {
+                self.history.push((self.program_counter, before));
+                self.program_counter = next;
+            }
+            Err(error) => return Event::Fault(error),
+        }
+
+        let (_, before) = self.history.last().unwrap();
+        let triggered = self.watchpoints.iter().find(|watchpoint| {
+            let (old, new) = (
+                before.get(watchpoint.cell),
+                self.cpu.state.get(watchpoint.cell),
+            );
+            old != new && watchpoint.value.is_none_or(|value| new == Some(&value))
+        });
+        match (triggered, self.halted()) {
+            (Some(watchpoint), _) => {
+                Event::Watchpoint(*watchpoint, self.cpu.state[watchpoint.cell])
+            }
+            (None, true) => Event::Halted,
+            (None, false) => Event::Stepped,
+        }
+    }
+
+    // undoes the last step; false when already at the start
+    pub fn back(&mut self) -> bool {
+        match self.history.pop() {
+            Some((program_counter, state)) => {
+                self.program_counter = program_counter;
+                self.cpu.state = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // steps until something worth stopping for, or `max_steps` instructions have run; a
+    // breakpoint on the current instruction doesn't stop it, so it can be resumed from one
+    pub fn resume(&mut self, max_steps: usize) -> Event {
+        for _ in 0..max_steps {
+            match self.step() {
+                Event::Stepped if self.breakpoints.contains(&self.program_counter) => {
+                    return Event::Breakpoint(self.program_counter)
+                }
+                Event::Stepped => {}
+                event => return event,
+            }
+        }
+        Event::StepLimit(max_steps)
+    }
+
+    // the program with `>` at the current instruction and `*` at breakpoints
+    pub fn listing(&self) -> String {
+        self.program
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| {
+                let current = if index == self.program_counter {
+                    '>'
+                } else {
+                    ' '
+                };
+                let breakpoint = if self.breakpoints.contains(&index) {
+                    '*'
+                } else {
+                    ' '
+                };
+                format!("{}{} {:>3}  {}", current, breakpoint, index, instruction)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// runs both programs in lockstep and prints their instructions and states next to each other,
+// marking the steps after which the states differ
+pub fn side_by_side<I: InstructionSemantics>(
+    left: &[I],
+    right: &[I],
+    cells: usize,
+    max_steps: usize,
+) -> String {
+    let mut debuggers = [
+        Debugger::new(left.to_vec(), cells),
+        Debugger::new(right.to_vec(), cells),
+    ];
+    let mut rows = Vec::new();
+    // a side stops at its first fault, or once its halt has been shown
+    let mut stopped = [false, false];
+
+    for step in 0..max_steps {
+        let finished = |side: usize| stopped[side] || debuggers[side].halted();
+        if finished(0) && finished(1) {
+            break;
+        }
+        let mut columns = [String::new(), String::new()];
+        for (side, debugger) in debuggers.iter_mut().enumerate() {
+            if stopped[side] {
+                continue;
+            }
+            let program_counter = debugger.program_counter();
+            let instruction = match debugger.current() {
+                Some(instruction) => instruction.to_string(),
+                None => {
+                    stopped[side] = true;
+                    columns[side] = "halted".to_string();
+                    continue;
+                }
+            };
+            columns[side] = match debugger.step() {
+                Event::Fault(error) => {
+                    stopped[side] = true;
+                    format!("{:>3}: {} fault: {}", program_counter, instruction, error)
+                }
+                _ => format!(
+                    "{:>3}: {:<12} {:?}",
+                    program_counter,
+                    instruction,
+                    debugger.state()
+                ),
+            };
+        }
+        let marker = if debuggers[0].state() != debuggers[1].state() {
+            "!="
+        } else {
+            "  "
+        };
+        rows.push((step + 1, columns, marker));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(_, columns, _)| columns[0].chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut lines = rows
+        .iter()
+        .map(|(step, columns, marker)| {
+            let padding = width - columns[0].chars().count();
+            format!(
+                "{:>4}  {}{}  {}  {}",
+                step,
+                columns[0],
+                " ".repeat(padding),
+                marker,
+                columns[1]
+            )
+            .trim_end()
+            .to_string()
+        })
+        .collect::<Vec<String>>();
+    lines.push(match debuggers[0].state() == debuggers[1].state() {
+        true => format!("final states equal: {:?}", debuggers[0].state()),
+        false => format!(
+            "final states differ: {:?} vs {:?}",
+            debuggers[0].state(),
+            debuggers[1].state()
+        ),
+    });
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn countdown() -> Vec<Instruction> {
+        // cell 0 counts down from 2 while cell 1 counts up
+        vec![
+            Instruction::LoadTo(0, 2),
+            Instruction::Jz(0, 5),
+            Instruction::Dec(0),
+            Instruction::Inc(1),
+            Instruction::Jmp(1),
+        ]
+    }
+
+    #[test]
+    fn steps_forward_and_back() {
+        let mut debugger = Debugger::new(countdown(), 2);
+        assert_eq!(debugger.step(), Event::Stepped);
+        assert_eq!(debugger.step(), Event::Stepped);
+        assert_eq!(debugger.step(), Event::Stepped);
+        assert_eq!(
+            (debugger.program_counter(), debugger.state()),
+            (3, &[1, 0][..])
+        );
+
+        assert!(debugger.back());
+        assert_eq!(
+            (debugger.program_counter(), debugger.state()),
+            (2, &[2, 0][..])
+        );
+        assert_eq!(debugger.steps(), 2);
+
+        assert_eq!(debugger.resume(100), Event::Halted);
+        assert_eq!(debugger.state(), &[0, 2]);
+        assert_eq!(debugger.step(), Event::Halted);
+
+        debugger.reset();
+        assert_eq!(
+            (debugger.program_counter(), debugger.state()),
+            (0, &[0, 0][..])
+        );
+        assert!(!debugger.back());
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_watchpoints() {
+        let mut debugger = Debugger::new(countdown(), 2);
+        debugger.add_breakpoint(3);
+        assert_eq!(debugger.resume(100), Event::Breakpoint(3));
+        // resuming from the breakpoint runs on to its next hit
+        assert_eq!(debugger.resume(100), Event::Breakpoint(3));
+        assert_eq!(debugger.state(), &[0, 1]);
+        assert!(debugger.remove_breakpoint(3));
+
+        let watchpoint = Watchpoint {
+            cell: 1,
+            value: Some(2),
+        };
+        debugger.watch(watchpoint);
+        assert_eq!(debugger.resume(100), Event::Watchpoint(watchpoint, 2));
+        assert_eq!(debugger.program_counter(), 4);
+        assert!(debugger.unwatch(1));
+
+        debugger.reset();
+        assert_eq!(debugger.resume(2), Event::StepLimit(2));
+    }
+
+    #[test]
+    fn reports_faults_without_stepping() {
+        let mut debugger = Debugger::new(vec![Instruction::Inc(0), Instruction::Inc(4)], 2);
+        debugger.step();
+        assert!(matches!(
+            debugger.resume(10),
+            Event::Fault(ExecError::CellOutOfBounds {
+                instruction: 1,
+                cell: 4
+            })
+        ));
+        assert_eq!(
+            (debugger.program_counter(), debugger.state()),
+            (1, &[1, 0][..])
+        );
+    }
+
+    #[test]
+    fn appends_instructions() {
+        let mut debugger = Debugger::new(Vec::new(), 2);
+        assert_eq!(debugger.push(Instruction::Load(3)), Ok(()));
+        assert_eq!(debugger.step(), Event::Halted);
+        assert_eq!(debugger.push(Instruction::Jmp(2)), Ok(()));
+        assert_eq!(
+            debugger.push(Instruction::Inc(2)),
+            Err(ExecError::CellOutOfBounds {
+                instruction: 2,
+                cell: 2
+            })
+        );
+        assert_eq!(
+            debugger.push(Instruction::Jmp(4)),
+            Err(ExecError::JumpOutOfBounds {
+                instruction: 2,
+                target: 4
+            })
+        );
+        assert_eq!(debugger.program().len(), 2);
+        assert_eq!(debugger.resume(10), Event::Halted);
+        assert_eq!(debugger.state(), &[3, 0]);
+    }
+
+    #[test]
+    fn compares_programs_side_by_side() {
+        let left = vec![Instruction::Load(3), Instruction::Swap(0, 1)];
+        let right = vec![Instruction::LoadTo(1, 3)];
+        let comparison = side_by_side(&left, &right, 2, 100);
+        assert_eq!(
+            comparison,
+            "   1    0: LOAD 3       [3, 0]  !=    0: LOADTO 1, 3  [0, 3]\n\
+             \x20  2    1: SWAP 0, 1    [0, 3]      halted\n\
+             final states equal: [0, 3]"
+        );
+    }
+}
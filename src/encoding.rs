@@ -0,0 +1,278 @@
+use std::fmt;
+
+use crate::cpu::Instruction;
+use crate::isa::InstructionSemantics;
+
+// Binary program format, all integers are unsigned LEB128 unless noted:
+//
+//   magic        4 bytes, "SOPM"
+//   version      1 byte, currently 1
+//   cells        number of memory cells the program needs
+//   length       number of instructions
+//   instructions `length` times: opcode byte followed by the instruction's operands in the
+//                order `Instruction::arguments` lists them
+//
+// Opcodes are fixed per instruction and never reused, see `opcode`.
+
+pub const MAGIC: [u8; 4] = *b"SOPM";
+pub const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub cells: usize,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    VarintOverflow,
+    InvalidOpcode(u8),
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "Not an encoded program"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported encoding version {}", version)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            DecodeError::VarintOverflow => write!(f, "Operand does not fit in a machine word"),
+            DecodeError::InvalidOpcode(opcode) => write!(f, "Invalid opcode 0x{:02x}", opcode),
+            DecodeError::TrailingBytes(count) => {
+                write!(f, "{} unexpected bytes after the last instruction", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn opcode(instruction: &Instruction) -> u8 {
+    match instruction {
+        Instruction::Load(_) => 0x00,
+        Instruction::Swap(_, _) => 0x01,
+        Instruction::Xor(_, _) => 0x02,
+        Instruction::Inc(_) => 0x03,
+        Instruction::Add(_, _) => 0x04,
+        Instruction::Sub(_, _) => 0x05,
+        Instruction::And(_, _) => 0x06,
+        Instruction::Or(_, _) => 0x07,
+        Instruction::Not(_) => 0x08,
+        Instruction::Dec(_) => 0x09,
+        Instruction::Shl(_, _) => 0x0a,
+        Instruction::Shr(_, _) => 0x0b,
+        Instruction::Mov(_, _) => 0x0c,
+        Instruction::Neg(_) => 0x0d,
+        Instruction::Jmp(_) => 0x0e,
+        Instruction::Jz(_, _) => 0x0f,
+        Instruction::Jnz(_, _) => 0x10,
+        Instruction::LoadTo(_, _) => 0x11,
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<usize, DecodeError> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as usize;
+            if shift >= usize::BITS || (bits << shift) >> shift != bits {
+                return Err(DecodeError::VarintOverflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn header(&mut self) -> Result<Header, DecodeError> {
+        for expected in MAGIC {
+            if self.byte().map_err(|_| DecodeError::BadMagic)? != expected {
+                return Err(DecodeError::BadMagic);
+            }
+        }
+        let version = self.byte()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        Ok(Header {
+            version,
+            cells: self.varint()?,
+            length: self.varint()?,
+        })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        let opcode = self.byte()?;
+        let instruction = match opcode {
+            0x00 => Instruction::Load(self.varint()?),
+            0x01 => Instruction::Swap(self.varint()?, self.varint()?),
+            0x02 => Instruction::Xor(self.varint()?, self.varint()?),
+            0x03 => Instruction::Inc(self.varint()?),
+            0x04 => Instruction::Add(self.varint()?, self.varint()?),
+            0x05 => Instruction::Sub(self.varint()?, self.varint()?),
+            0x06 => Instruction::And(self.varint()?, self.varint()?),
+            0x07 => Instruction::Or(self.varint()?, self.varint()?),
+            0x08 => Instruction::Not(self.varint()?),
+            0x09 => Instruction::Dec(self.varint()?),
+            0x0a => Instruction::Shl(self.varint()?, self.varint()?),
+            0x0b => Instruction::Shr(self.varint()?, self.varint()?),
+            0x0c => Instruction::Mov(self.varint()?, self.varint()?),
+            0x0d => Instruction::Neg(self.varint()?),
+            0x0e => Instruction::Jmp(self.varint()?),
+            0x0f => Instruction::Jz(self.varint()?, self.varint()?),
+            0x10 => Instruction::Jnz(self.varint()?, self.varint()?),
+            0x11 => Instruction::LoadTo(self.varint()?, self.varint()?),
+            _ => return Err(DecodeError::InvalidOpcode(opcode)),
+        };
+        Ok(instruction)
+    }
+}
+
+// the number of cells a CPU needs to run `program`
+pub fn required_cells(program: &[Instruction]) -> usize {
+    program
+        .iter()
+        .flat_map(|instruction| instruction.reads().into_iter().chain(instruction.writes()))
+        .map(|cell| cell + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+pub fn encode(program: &[Instruction]) -> Vec<u8> {
+    encode_with_cells(program, required_cells(program))
+}
+
+pub fn encode_with_cells(program: &[Instruction], cells: usize) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    write_varint(&mut bytes, cells);
+    write_varint(&mut bytes, program.len());
+
+    for instruction in program {
+        bytes.push(opcode(instruction));
+        for argument in instruction.arguments() {
+            write_varint(&mut bytes, argument);
+        }
+    }
+    bytes
+}
+
+pub fn decode_header(bytes: &[u8]) -> Result<Header, DecodeError> {
+    Reader { bytes, position: 0 }.header()
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    let mut reader = Reader { bytes, position: 0 };
+    let header = reader.header()?;
+
+    let program = (0..header.length)
+        .map(|_| reader.instruction())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if reader.position != bytes.len() {
+        return Err(DecodeError::TrailingBytes(bytes.len() - reader.position));
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::InstructionSet;
+    use crate::parser::{output, parse};
+
+    #[test]
+    fn can_encode() {
+        let program = vec![Instruction::Load(200), Instruction::Swap(0, 3)];
+        let bytes = encode(&program);
+        assert_eq!(
+            bytes,
+            vec![b'S', b'O', b'P', b'M', 1, 4, 2, 0x00, 0xc8, 0x01, 0x01, 0x00, 0x03]
+        );
+        assert_eq!(
+            decode_header(&bytes),
+            Ok(Header {
+                version: 1,
+                cells: 4,
+                length: 2
+            })
+        );
+    }
+
+    #[test]
+    fn can_round_trip_every_instruction() {
+        let program = InstructionSet::all().instructions(3, 3, 2);
+        assert_eq!(decode(&encode(&program)), Ok(program.clone()));
+
+        let large = vec![Instruction::LoadTo(1 << 40, usize::MAX)];
+        assert_eq!(decode(&encode(&large)), Ok(large));
+    }
+
+    #[test]
+    fn round_trips_with_text_format() {
+        let assembly = "LOAD 3\nSWAP 0, 1\nXOR 1, 2\nINC 5\nJNZ 1, 0\nLOADTO 4, 300";
+        let program = parse(assembly).unwrap();
+        let decoded = decode(&encode(&program)).unwrap();
+        assert_eq!(output(&decoded), assembly);
+        assert_eq!(parse(&output(&decoded)).unwrap(), program);
+    }
+
+    #[test]
+    fn cant_decode_invalid_input() {
+        let bytes = encode(&[Instruction::Xor(1, 2)]);
+        assert_eq!(decode(b"SOP"), Err(DecodeError::BadMagic));
+        assert_eq!(decode(b"ELF\x01"), Err(DecodeError::BadMagic));
+        assert_eq!(
+            decode(b"SOPM\x02\x00\x00"),
+            Err(DecodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            decode(b"SOPM\x01\x01\x01\xff"),
+            Err(DecodeError::InvalidOpcode(0xff))
+        );
+        assert_eq!(
+            decode(&[bytes.as_slice(), &[0]].concat()),
+            Err(DecodeError::TrailingBytes(1))
+        );
+        assert_eq!(
+            decode(b"SOPM\x01\x01\x01\x00\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f"),
+            Err(DecodeError::VarintOverflow)
+        );
+    }
+}
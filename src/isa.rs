@@ -0,0 +1,136 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parser::ParseError;
+
+// everything the CPU, parser and search engines need to know about an instruction set;
+// `cpu::Instruction` is the built-in implementation
+pub trait InstructionSemantics:
+    Clone + fmt::Debug + PartialEq + fmt::Display + FromStr<Err = ParseError> + Send + Sync + 'static
+{
+    // selects which instructions a search enumerates, e.g. `cpu::InstructionSet`
+    type Alphabet: Clone + fmt::Debug + PartialEq + Send + Sync + 'static;
+
+    // every concrete instruction of `alphabet` a `program_length` long program may contain
+    fn enumerate(
+        alphabet: &Self::Alphabet,
+        max_memory_cells: usize,
+        max_value: usize,
+        program_length: usize,
+    ) -> Vec<Self>;
+
+    // applies the instruction at `program_counter` to `state` and returns the index of the
+    // next instruction; only called once `reads` and `writes` are known to be in bounds
+    fn execute(&self, state: &mut [usize], program_counter: usize) -> usize;
+
+    fn reads(&self) -> Vec<usize>;
+
+    fn writes(&self) -> Vec<usize>;
+
+    fn jump_target(&self) -> Option<usize> {
+        None
+    }
+
+    // whether the next instruction may run after this one, i.e. the jump can be skipped
+    fn falls_through(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchConfig;
+    use crate::cpu::CPU;
+    use crate::parser::{output, parse_program};
+    use crate::superoptimizer::superoptimize_with_config;
+
+    // an accumulator machine with no notion of cells beyond cell 0
+    #[derive(Debug, Clone, PartialEq)]
+    enum Acc {
+        Set(usize),
+        Double,
+        Halve,
+    }
+
+    impl fmt::Display for Acc {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Acc::Set(value) => write!(f, "set {}", value),
+                Acc::Double => write!(f, "double"),
+                Acc::Halve => write!(f, "halve"),
+            }
+        }
+    }
+
+    impl FromStr for Acc {
+        type Err = ParseError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["set", value] => value.parse().map(Acc::Set).map_err(ParseError::ArgParse),
+                ["double"] => Ok(Acc::Double),
+                ["halve"] => Ok(Acc::Halve),
+                _ => Err(ParseError::InvalidOp),
+            }
+        }
+    }
+
+    impl InstructionSemantics for Acc {
+        type Alphabet = bool;
+
+        fn enumerate(
+            allow_halve: &bool,
+            _max_memory_cells: usize,
+            max_value: usize,
+            _program_length: usize,
+        ) -> Vec<Acc> {
+            let mut instructions = (0..max_value).map(Acc::Set).collect::<Vec<_>>();
+            instructions.push(Acc::Double);
+            if *allow_halve {
+                instructions.push(Acc::Halve);
+            }
+            instructions
+        }
+
+        fn execute(&self, state: &mut [usize], program_counter: usize) -> usize {
+            match self {
+                Acc::Set(value) => state[0] = *value,
+                Acc::Double => state[0] *= 2,
+                Acc::Halve => state[0] /= 2,
+            }
+            program_counter + 1
+        }
+
+        fn reads(&self) -> Vec<usize> {
+            match self {
+                Acc::Set(_) => vec![],
+                Acc::Double | Acc::Halve => vec![0],
+            }
+        }
+
+        fn writes(&self) -> Vec<usize> {
+            vec![0]
+        }
+    }
+
+    #[test]
+    fn can_run_custom_instruction_set() {
+        let program = parse_program::<Acc>("set 3\ndouble\nhalve\ndouble").unwrap();
+        assert_eq!(output(&program), "set 3\ndouble\nhalve\ndouble");
+
+        let mut cpu = CPU::new(1);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, vec![6]);
+    }
+
+    #[test]
+    fn can_superoptimize_custom_instruction_set() {
+        let config = SearchConfig::<Acc>::for_instruction_set(4, 1, 3, false);
+        let program = superoptimize_with_config(&config, &[8]).unwrap();
+        assert_eq!(program, vec![Acc::Set(2), Acc::Double, Acc::Double]);
+
+        assert!(!Acc::enumerate(&false, 1, 3, 3).contains(&Acc::Halve));
+        assert!(Acc::enumerate(&true, 1, 3, 3).contains(&Acc::Halve));
+    }
+}
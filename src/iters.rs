@@ -14,6 +14,50 @@ pub fn product<T: Clone>(elements: &Vec<T>, times: usize) -> Vec<Vec<T>> {
     result
 }
 
+// lazy equivalent of `product`, yielding the combinations in the same order
+pub struct Product<'a, T> {
+    elements: &'a [T],
+    indices: Vec<usize>,
+    done: bool,
+}
+
+pub fn product_iter<T: Clone>(elements: &[T], times: usize) -> Product<'_, T> {
+    Product {
+        elements,
+        indices: vec![0; times],
+        done: elements.is_empty() && times > 0,
+    }
+}
+
+impl<T: Clone> Iterator for Product<'_, T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.done {
+            return None;
+        }
+
+        let combination = self
+            .indices
+            .iter()
+            .map(|index| self.elements[*index].clone())
+            .collect();
+
+        // advance the indices like an odometer whose first digit turns fastest
+        self.done = true;
+        for index in self.indices.iter_mut() {
+            *index += 1;
+            if *index < self.elements.len() {
+                self.done = false;
+                break;
+            }
+            *index = 0;
+        }
+
+        Some(combination)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -82,4 +126,14 @@ mod tests {
         assert!(result.contains(&vec!["INC", "XOR"]));
         assert!(result.contains(&vec!["INC", "INC"]));
     }
+
+    #[test]
+    fn product_iter_matches_product() {
+        let elements = vec!["LOAD", "SWAP", "XOR"];
+        for times in 0..4 {
+            let lazy = product_iter(&elements, times).collect::<Vec<_>>();
+            assert_eq!(lazy, product(&elements, times));
+        }
+        assert_eq!(product_iter::<i32>(&[], 2).count(), 0);
+    }
 }
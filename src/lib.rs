@@ -1,8 +1,27 @@
+pub mod analysis;
+pub mod batch;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod cegis;
+pub mod config;
 pub mod cpu;
+pub mod debugger;
+pub mod encoding;
+pub mod isa;
 pub mod iters;
 pub mod operations;
 pub mod parser;
+pub mod peephole;
+pub mod report;
+pub mod rules;
+pub mod sat;
+pub mod smt;
+#[cfg(test)]
+pub mod strategies;
 pub mod superoptimizer;
 pub mod superoptimizer_async;
 pub mod superoptimizer_rayon;
+pub mod superoptimizer_sat;
 pub mod superoptimizer_threads;
+pub mod symbolic;
+pub mod verify;
@@ -0,0 +1,1128 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+
+use superoptimusprime::analysis;
+use superoptimusprime::batch::{self, BatchJob, BatchOptions};
+use superoptimusprime::cache::Cache;
+use superoptimusprime::cegis;
+use superoptimusprime::config::{SearchConfig, SearchLimits, DEFAULT_MAX_STEPS};
+use superoptimusprime::cpu::{to_signed, validate, Instruction, InstructionSet, CPU};
+use superoptimusprime::debugger::{self, Debugger, Event, Watchpoint};
+use superoptimusprime::encoding;
+use superoptimusprime::parser::{self, Assembly, OutputOptions, ParseOptions};
+use superoptimusprime::peephole::{self, PeepholeOptions};
+use superoptimusprime::report::{Backend, SearchReport};
+use superoptimusprime::rules::RuleDatabase;
+use superoptimusprime::smt;
+use superoptimusprime::symbolic;
+use superoptimusprime::verify::{self, Outcome, VerifyError};
+
+const USAGE: &str = "usage: superoptimusprime <command> [options]
+
+commands:
+  run <program>                execute a program and print the final state
+  optimize <program>           search for the shortest program reaching the same state
+  verify <program> <program>   check that two programs leave the same states
+  convert <input> <output>     translate between assembly, binary and JSON programs
+  analyze <program>            show the cells live after each instruction, dead stores and
+                               which instruction's values each one reads
+  explain <program>            print each cell's final value as a formula of the initial cells
+  debug [<program>]            step through a program interactively (`help` lists commands),
+                               or type one in from an empty program with 4 cells
+  compare <program> <program>  run two programs in lockstep and show their states side by side
+  batch <program|dir>...       optimize many programs, or every file in each directory, in
+                               parallel and print a Markdown report
+  rules <output>               generate a database of rewrite rules from every short program
+  smt <program> [<program>]    print an SMT-LIB2 query: whether two programs are equivalent, or
+                               whether a program of --max-length instructions reaches the same state
+
+programs are read by extension: .bin (binary encoding), .json, anything else is assembly;
+`-` reads standard input or writes standard output
+
+options:
+  --cells N           memory cells (default: .cells, the binary header or what the program uses)
+  --max-steps N       instructions a run may execute (default: 1000)
+  --domain N          verify (or optimize for) every initial state with cells below N
+                      (verify defaults to 1: only all zeros)
+  --signed            read and print immediates and states as signed values
+  --json              print results as JSON
+  --backend NAME      optimize with simple, rayon, threads, async or sat (default: rayon)
+  --max-length N      longest program optimize tries (default: the input's length)
+  --window N          optimize N instructions at a time, for programs too long to search whole
+  --rules FILE        optimize by rewriting with a rule database instead of searching
+  --dead-stores       optimize by removing writes nothing reads instead of searching
+  --cache DIR         reuse and record search results in DIR (default: $SUPEROPTIMUSPRIME_CACHE)
+  --max-value N       immediates optimize tries are below N (default: one past the input's largest,
+                      at most 256)
+  --ops A,B,...       mnemonics optimize tries (default: the ones the input uses)
+  --from, --to FMT    asm, bin or json, overriding the extension
+  --hex, --lowercase  assembly output style
+  --budget-ms N       time all of batch may take
+  --budget-programs N candidate programs all of batch may try
+  --outputs A,B,...   cells that count at the end for analyze and --dead-stores (default: all)
+  --dot               print analyze's dependencies as a Graphviz graph
+  --width N           bits per cell in SMT-LIB2 queries (default: 64)
+";
+
+// exit codes besides success
+const EXIT_NEGATIVE: u8 = 1; // no program found, or the programs differ
+const EXIT_USAGE: u8 = 2;
+const EXIT_INPUT: u8 = 3;
+const EXIT_EXECUTION: u8 = 4;
+
+const FLAGS: [&str; 6] = ["json", "signed", "hex", "lowercase", "dot", "dead-stores"];
+
+#[derive(Debug, PartialEq)]
+enum Failure {
+    Usage(String),
+    Input(String),
+    Execution(String),
+}
+
+impl Failure {
+    fn code(&self) -> u8 {
+        match self {
+            Failure::Usage(_) => EXIT_USAGE,
+            Failure::Input(_) => EXIT_INPUT,
+            Failure::Execution(_) => EXIT_EXECUTION,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            Failure::Input(message) | Failure::Execution(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    positional: Vec<String>,
+    flags: HashSet<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, Failure> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if FLAGS.contains(&name) => {
+                    parsed.flags.insert(name.to_string());
+                }
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| Failure::Usage(format!("--{} needs a value", name)))?;
+                    parsed.options.insert(name.to_string(), value);
+                }
+                None => parsed.positional.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    fn value<T: FromStr>(&self, name: &str) -> Result<Option<T>, Failure> {
+        self.options
+            .get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| Failure::Usage(format!("Invalid value {} for --{}", value, name)))
+            })
+            .transpose()
+    }
+
+    // exactly `count` positional arguments after the command
+    fn files(&self, count: usize) -> Result<&[String], Failure> {
+        match self.positional.len() == count + 1 {
+            true => Ok(&self.positional[1..]),
+            false => Err(Failure::Usage(format!(
+                "{} expects {} file(s)",
+                self.positional[0], count
+            ))),
+        }
+    }
+
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            signed: self.flag("signed"),
+            ..Default::default()
+        }
+    }
+
+    fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            signed: self.flag("signed"),
+            hex: self.flag("hex"),
+            lowercase: self.flag("lowercase"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Assembly,
+    Binary,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asm" => Ok(Format::Assembly),
+            "bin" => Ok(Format::Binary),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format {}", s)),
+        }
+    }
+}
+
+// `explicit` (from --from or --to) wins over the extension of `path`
+fn format_of(path: &str, explicit: Option<Format>) -> Format {
+    explicit.unwrap_or_else(|| match path.rsplit_once('.') {
+        Some((_, "bin")) => Format::Binary,
+        Some((_, "json")) => Format::Json,
+        _ => Format::Assembly,
+    })
+}
+
+fn read_bytes(path: &str) -> Result<Vec<u8>, Failure> {
+    let mut bytes = Vec::new();
+    match path {
+        "-" => std::io::stdin().read_to_end(&mut bytes).map(|_| bytes),
+        _ => fs::read(path),
+    }
+    .map_err(|error| Failure::Input(format!("{}: {}", path, error)))
+}
+
+fn read_program(path: &str, format: Format, options: &ParseOptions) -> Result<Assembly, Failure> {
+    let bytes = read_bytes(path)?;
+    let invalid = |message: String| Failure::Input(format!("{}: {}", path, message));
+    match format {
+        Format::Assembly => {
+            let source = String::from_utf8(bytes).map_err(|error| invalid(error.to_string()))?;
+            parser::parse_assembly_with(&source, options).map_err(|errors| {
+                let messages = errors
+                    .iter()
+                    .map(|error| format!("{}: {}", path, error))
+                    .collect::<Vec<String>>();
+                Failure::Input(messages.join("\n"))
+            })
+        }
+        Format::Binary => {
+            let header = encoding::decode_header(&bytes).map_err(|e| invalid(e.to_string()))?;
+            let program = encoding::decode(&bytes).map_err(|e| invalid(e.to_string()))?;
+            Ok(Assembly {
+                program,
+                cells: Some(header.cells),
+                target: None,
+            })
+        }
+        Format::Json => {
+            let program = serde_json::from_slice::<Vec<Instruction>>(&bytes)
+                .map_err(|error| invalid(error.to_string()))?;
+            Ok(Assembly {
+                program,
+                cells: None,
+                target: None,
+            })
+        }
+    }
+}
+
+fn write_program(
+    path: &str,
+    assembly: &Assembly,
+    format: Format,
+    options: &OutputOptions,
+) -> Result<(), Failure> {
+    let bytes = match format {
+        Format::Assembly => {
+            // directives first, so nothing the input declared is lost
+            let mut lines = Vec::new();
+            if let Some(cells) = assembly.cells {
+                lines.push(format!(".cells {}", cells));
+            }
+            if let Some(target) = &assembly.target {
+                lines.push(format!(".target {}", format_state(target, options.signed)));
+            }
+            lines.push(parser::output_with(&assembly.program, options));
+            (lines.join("\n") + "\n").into_bytes()
+        }
+        Format::Binary => match assembly.cells {
+            Some(cells) => encoding::encode_with_cells(&assembly.program, cells),
+            None => encoding::encode(&assembly.program),
+        },
+        Format::Json => serde_json::to_vec_pretty(&assembly.program)
+            .map_err(|error| Failure::Input(error.to_string()))?,
+    };
+    match path {
+        "-" => std::io::stdout().write_all(&bytes),
+        _ => fs::write(path, bytes),
+    }
+    .map_err(|error| Failure::Input(format!("{}: {}", path, error)))
+}
+
+fn format_state(state: &[usize], signed: bool) -> String {
+    let values = match signed {
+        true => to_signed(state)
+            .iter()
+            .map(|value| value.to_string())
+            .collect(),
+        false => state
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>(),
+    };
+    values.join(", ")
+}
+
+// --cells, then what the input declares, then the fewest cells the program touches
+fn cells_for(args: &Args, assembly: &Assembly) -> Result<usize, Failure> {
+    Ok(args.value("cells")?.unwrap_or_else(|| {
+        assembly
+            .cells
+            .unwrap_or_else(|| encoding::required_cells(&assembly.program))
+    }))
+}
+
+fn execute(args: &Args, program: &[Instruction], cells: usize) -> Result<Vec<usize>, Failure> {
+    let max_steps = args.value("max-steps")?.unwrap_or(DEFAULT_MAX_STEPS);
+    let mut cpu = CPU::new(cells).with_max_steps(max_steps);
+    cpu.try_execute(program)
+        .map_err(|error| Failure::Execution(error.to_string()))?;
+    Ok(cpu.state)
+}
+
+fn load(args: &Args, path: &str) -> Result<Assembly, Failure> {
+    let format = format_of(path, args.value("from")?);
+    read_program(path, format, &args.parse_options())
+}
+
+fn run(args: &Args) -> Result<bool, Failure> {
+    let path = &args.files(1)?[0];
+    let assembly = load(args, path)?;
+    let cells = cells_for(args, &assembly)?;
+    let state = execute(args, &assembly.program, cells)?;
+
+    match args.flag("json") {
+        true => {
+            let state = match args.flag("signed") {
+                true => serde_json::json!(to_signed(&state)),
+                false => serde_json::json!(state),
+            };
+            println!("{}", serde_json::json!({ "cells": cells, "state": state }));
+        }
+        false => println!("{}", format_state(&state, args.flag("signed"))),
+    }
+    Ok(true)
+}
+
+fn search_limits(args: &Args) -> Result<SearchLimits, Failure> {
+    let instruction_set = match args.options.get("ops") {
+        Some(ops) => {
+            let ops = ops.split(',').map(str::trim).collect::<Vec<&str>>();
+            if let Some(op) = ops.iter().find(|op| !InstructionSet::all().contains(op)) {
+                return Err(Failure::Usage(format!("Unknown operation {}", op)));
+            }
+            let instruction_set = InstructionSet::new(&ops);
+            Some(match args.flag("signed") {
+                true => instruction_set.signed(),
+                false => instruction_set,
+            })
+        }
+        None => None,
+    };
+    Ok(SearchLimits {
+        max_instructions_length: args.value("max-length")?,
+        max_value: args.value("max-value")?,
+        instruction_set,
+        max_steps: args.value("max-steps")?,
+    })
+}
+
+fn search_config(args: &Args, assembly: &Assembly, cells: usize) -> Result<SearchConfig, Failure> {
+    let limits = search_limits(args)?;
+    Ok(SearchConfig::covering(&assembly.program, cells, args.flag("signed")).limited(&limits))
+}
+
+// with --domain the result has to match the input on every initial state, not just zeros
+fn optimize_for_all_inputs(
+    args: &Args,
+    assembly: &Assembly,
+    cells: usize,
+    domain: usize,
+) -> Result<bool, Failure> {
+    let config = search_config(args, assembly, cells)?;
+    let values = (0..domain).collect::<Vec<usize>>();
+    let synthesis = cegis::synthesize(&assembly.program, &config, &values)
+        .map_err(|error| Failure::Execution(error.to_string()))?;
+
+    match (args.flag("json"), &synthesis.program) {
+        (true, _) => println!(
+            "{}",
+            serde_json::json!({
+                "domain": domain,
+                "config": config,
+                "program": synthesis.program,
+                "iterations": synthesis.iterations,
+                "tests": synthesis.tests,
+            })
+        ),
+        (false, Some(program)) => {
+            println!("{}", parser::output_with(program, &args.output_options()))
+        }
+        (false, None) => eprintln!("No program found"),
+    }
+    Ok(synthesis.program.is_some())
+}
+
+// with --window the input is shortened a few instructions at a time, staying equivalent on
+// every initial state
+fn optimize_windows(
+    args: &Args,
+    assembly: &Assembly,
+    cells: usize,
+    window: usize,
+) -> Result<bool, Failure> {
+    let options = PeepholeOptions {
+        window,
+        limits: search_limits(args)?,
+        signed: args.flag("signed"),
+    };
+    let program = peephole::optimize(&assembly.program, cells, &options)
+        .map_err(|error| Failure::Execution(error.to_string()))?;
+
+    match args.flag("json") {
+        true => println!(
+            "{}",
+            serde_json::json!({
+                "window": window,
+                "original_length": assembly.program.len(),
+                "program": program,
+            })
+        ),
+        false => println!("{}", parser::output_with(&program, &args.output_options())),
+    }
+    Ok(true)
+}
+
+fn optimize_with_rules(args: &Args, assembly: &Assembly, path: &str) -> Result<bool, Failure> {
+    let text = String::from_utf8(read_bytes(path)?)
+        .map_err(|error| Failure::Input(format!("{}: {}", path, error)))?;
+    let database = RuleDatabase::from_text(&text)
+        .map_err(|error| Failure::Input(format!("{}: {}", path, error)))?;
+    let program = database.apply(&assembly.program);
+
+    match args.flag("json") {
+        true => println!(
+            "{}",
+            serde_json::json!({
+                "rules": database.rules().len(),
+                "original_length": assembly.program.len(),
+                "program": program,
+            })
+        ),
+        false => println!("{}", parser::output_with(&program, &args.output_options())),
+    }
+    Ok(true)
+}
+
+// --outputs, or every cell
+fn outputs_for(args: &Args, cells: usize) -> Result<Vec<usize>, Failure> {
+    let outputs = match args.options.get("outputs") {
+        Some(outputs) => outputs
+            .split(',')
+            .map(|cell| {
+                cell.trim()
+                    .parse()
+                    .map_err(|_| Failure::Usage(format!("Invalid value {} for --outputs", outputs)))
+            })
+            .collect::<Result<Vec<usize>, Failure>>()?,
+        None => (0..cells).collect(),
+    };
+    match outputs.iter().find(|&&cell| cell >= cells) {
+        Some(cell) => Err(Failure::Usage(format!(
+            "Output cell {} is out of bounds",
+            cell
+        ))),
+        None => Ok(outputs),
+    }
+}
+
+fn optimize_dead_stores(args: &Args, assembly: &Assembly, cells: usize) -> Result<bool, Failure> {
+    let outputs = outputs_for(args, cells)?;
+    let program = analysis::eliminate_dead_stores(&assembly.program, cells, &outputs)
+        .map_err(|error| Failure::Execution(error.to_string()))?;
+
+    match args.flag("json") {
+        true => println!(
+            "{}",
+            serde_json::json!({
+                "outputs": outputs,
+                "original_length": assembly.program.len(),
+                "program": program,
+            })
+        ),
+        false => println!("{}", parser::output_with(&program, &args.output_options())),
+    }
+    Ok(true)
+}
+
+// without a .target the input itself reaches the state, so it answers whenever the search found
+// nothing shorter, say because its immediates lie past the values searched
+fn fall_back_to_input(found: &mut Option<Vec<Instruction>>, assembly: &Assembly) {
+    let shorter = |program: &Vec<Instruction>| program.len() < assembly.program.len();
+    if assembly.target.is_none() && !found.as_ref().is_some_and(shorter) {
+        *found = Some(assembly.program.clone());
+    }
+}
+
+fn optimize(args: &Args) -> Result<bool, Failure> {
+    let path = &args.files(1)?[0];
+    let assembly = load(args, path)?;
+    let cells = cells_for(args, &assembly)?;
+    if let Some(domain) = args.value("domain")? {
+        return optimize_for_all_inputs(args, &assembly, cells, domain);
+    }
+    if let Some(window) = args.value("window")? {
+        return optimize_windows(args, &assembly, cells, window);
+    }
+    if let Some(path) = args.options.get("rules") {
+        return optimize_with_rules(args, &assembly, path);
+    }
+    if args.flag("dead-stores") {
+        return optimize_dead_stores(args, &assembly, cells);
+    }
+    let target = match &assembly.target {
+        Some(target) => target.clone(),
+        None => execute(args, &assembly.program, cells)?,
+    };
+    let config = search_config(args, &assembly, cells)?;
+    let backend = args.value("backend")?.unwrap_or(Backend::Rayon);
+
+    let mut report = match args.options.get("cache") {
+        Some(directory) => {
+            let cache = Cache::open(directory)
+                .map_err(|error| Failure::Input(format!("{}: {}", directory, error)))?;
+            SearchReport::run_with_cache(backend, &cache, &config, &target)
+        }
+        None => SearchReport::run(backend, &config, &target),
+    }
+    .map_err(|error| Failure::Execution(error.to_string()))?;
+    fall_back_to_input(&mut report.result.program, &assembly);
+    let found = report.result.program.is_some();
+    match (args.flag("json"), &report.result.program) {
+        (true, _) => println!(
+            "{}",
+            report
+                .to_json()
+                .map_err(|error| Failure::Execution(error.to_string()))?
+        ),
+        (false, Some(program)) => {
+            println!("{}", parser::output_with(program, &args.output_options()))
+        }
+        (false, None) => match report.result.width {
+            Some(width) => eprintln!("No program within {}-bit values", width),
+            None => eprintln!("No program found"),
+        },
+    }
+    Ok(found)
+}
+
+fn outcome_json(outcome: &Outcome) -> serde_json::Value {
+    match outcome {
+        Ok(state) => serde_json::json!({ "state": state }),
+        Err(error) => serde_json::json!({ "fault": error.to_string() }),
+    }
+}
+
+fn verify(args: &Args) -> Result<bool, Failure> {
+    let files = args.files(2)?;
+    let left = load(args, &files[0])?;
+    let right = load(args, &files[1])?;
+    let cells = cells_for(args, &left)?.max(cells_for(args, &right)?);
+    let domain = args.value("domain")?.unwrap_or(1);
+    let max_steps = args.value("max-steps")?.unwrap_or(DEFAULT_MAX_STEPS);
+    let values = (0..domain).collect::<Vec<usize>>();
+    let result =
+        match verify::equivalent_within(&left.program, &right.program, cells, &values, max_steps) {
+            Ok(()) => Ok(()),
+            Err(VerifyError::Counterexample(counterexample)) => Err(counterexample),
+            Err(error) => return Err(Failure::Execution(error.to_string())),
+        };
+
+    match (args.flag("json"), &result) {
+        (true, _) => {
+            let counterexample = result.as_ref().err().map(|counterexample| {
+                serde_json::json!({
+                    "input": counterexample.input,
+                    "left": outcome_json(&counterexample.left),
+                    "right": outcome_json(&counterexample.right),
+                })
+            });
+            println!(
+                "{}",
+                serde_json::json!({
+                    "equivalent": result.is_ok(),
+                    "cells": cells,
+                    "domain": domain,
+                    "counterexample": counterexample,
+                })
+            )
+        }
+        (false, Ok(())) => println!("equivalent"),
+        (false, Err(counterexample)) => println!("not equivalent: {}", counterexample),
+    }
+    Ok(result.is_ok())
+}
+
+fn cell_list(cells: &[bool]) -> String {
+    let live = (0..cells.len())
+        .filter(|&cell| cells[cell])
+        .map(|cell| format!("c{}", cell))
+        .collect::<Vec<String>>();
+    match live.is_empty() {
+        true => "-".to_string(),
+        false => live.join(" "),
+    }
+}
+
+fn analyze(args: &Args) -> Result<bool, Failure> {
+    let path = &args.files(1)?[0];
+    let assembly = load(args, path)?;
+    let cells = cells_for(args, &assembly)?;
+    let outputs = outputs_for(args, cells)?;
+    let program = &assembly.program;
+    validate(program, cells).map_err(|error| Failure::Execution(error.to_string()))?;
+    if args.flag("dot") {
+        println!("{}", analysis::dependency_graph(program, cells, &outputs));
+        return Ok(true);
+    }
+
+    let live = analysis::live_cells(program, cells, &outputs);
+    let live_after = (0..program.len())
+        .map(|index| analysis::live_after(program, &live, index))
+        .collect::<Vec<_>>();
+    let dead = analysis::dead_stores(program, cells, &outputs);
+    let chains = analysis::def_use_chains(program, cells, &outputs);
+
+    match args.flag("json") {
+        true => println!(
+            "{}",
+            serde_json::json!({
+                "cells": cells,
+                "outputs": outputs,
+                "live_after": live_after,
+                "dead_stores": dead,
+                "chains": chains,
+            })
+        ),
+        false => {
+            let output_options = args.output_options();
+            for (index, instruction) in program.iter().enumerate() {
+                let text = parser::output_with(&[*instruction], &output_options);
+                let note = match dead.contains(&index) {
+                    true => "  (dead store)",
+                    false => "",
+                };
+                println!(
+                    "{:>3}: {:<16} live after: {}{}",
+                    index,
+                    text,
+                    cell_list(&live_after[index]),
+                    note
+                );
+            }
+            for chain in &chains {
+                let from = chain
+                    .definition
+                    .map_or("start".to_string(), |index| index.to_string());
+                let to = match chain.user == program.len() {
+                    true => "end".to_string(),
+                    false => chain.user.to_string(),
+                };
+                println!("c{}: {} -> {}", chain.cell, from, to);
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn explain(args: &Args) -> Result<bool, Failure> {
+    let path = &args.files(1)?[0];
+    let assembly = load(args, path)?;
+    let cells = cells_for(args, &assembly)?;
+    let max_steps = args.value("max-steps")?.unwrap_or(DEFAULT_MAX_STEPS);
+    let formulas = symbolic::execute(&assembly.program, cells, max_steps)
+        .map_err(|error| Failure::Execution(error.to_string()))?;
+
+    match args.flag("json") {
+        true => {
+            let formulas = formulas.iter().map(ToString::to_string).collect::<Vec<_>>();
+            println!(
+                "{}",
+                serde_json::json!({ "cells": cells, "formulas": formulas })
+            );
+        }
+        false => {
+            for (cell, formula) in formulas.iter().enumerate() {
+                println!("c{} = {}", cell, formula);
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn smt(args: &Args) -> Result<bool, Failure> {
+    let width = args.value("width")?.unwrap_or(usize::BITS);
+    if width == 0 {
+        return Err(Failure::Usage("--width must be at least 1".to_string()));
+    }
+
+    let query = match args.positional.len() {
+        3 => {
+            let files = args.files(2)?;
+            let left = load(args, &files[0])?;
+            let right = load(args, &files[1])?;
+            let cells = cells_for(args, &left)?.max(cells_for(args, &right)?);
+            smt::equivalence_query(&left.program, &right.program, cells, width)
+        }
+        _ => {
+            let path = &args.files(1)?[0];
+            let assembly = load(args, path)?;
+            let cells = cells_for(args, &assembly)?;
+            let target = match &assembly.target {
+                Some(target) => target.clone(),
+                None => execute(args, &assembly.program, cells)?,
+            };
+            let config = search_config(args, &assembly, cells)?;
+            smt::synthesis_query(&config, &target, config.max_instructions_length, width)
+        }
+    };
+    let query = query.map_err(|error| Failure::Execution(error.to_string()))?;
+    println!("{}", query);
+    Ok(true)
+}
+
+// every program of up to --max-length (default 3) instructions over --cells (default 2) cells
+fn rules(args: &Args) -> Result<bool, Failure> {
+    let path = &args.files(1)?[0];
+    let cells = args.value("cells")?.unwrap_or(2);
+    let config = SearchConfig::new(3, cells, 2).limited(&search_limits(args)?);
+    let text = RuleDatabase::generate(&config).to_text();
+    match path.as_str() {
+        "-" => std::io::stdout().write_all(text.as_bytes()),
+        _ => fs::write(path, text),
+    }
+    .map_err(|error| Failure::Input(format!("{}: {}", path, error)))?;
+    Ok(true)
+}
+
+fn convert(args: &Args) -> Result<bool, Failure> {
+    let files = args.files(2)?;
+    let assembly = load(args, &files[0])?;
+    let format = format_of(&files[1], args.value("to")?);
+    write_program(&files[1], &assembly, format, &args.output_options())?;
+    Ok(true)
+}
+
+// cells to debug an empty program with, unless --cells says otherwise
+const DEFAULT_DEBUG_CELLS: usize = 4;
+
+const DEBUG_HELP: &str = "step [N], s      execute the next N instructions (default 1)
+back [N], b      undo the last N steps
+continue, c      run until a breakpoint, a watchpoint, a fault or the end
+break I          stop before instruction I
+delete I         remove the breakpoint on instruction I
+watch C [V]      stop once cell C changes, or once it becomes V
+unwatch C        remove the watchpoints on cell C
+print, p         show the current instruction and state
+list, l          show the program with the current instruction and breakpoints
+reset            start over, keeping break- and watchpoints
+<instruction>    add an instruction to the end of the program, running it if at the end
+quit, q          leave the debugger";
+
+fn describe(debugger: &Debugger, signed: bool) -> String {
+    let location = match debugger.current() {
+        Some(instruction) => format!("{}: {}", debugger.program_counter(), instruction),
+        None => format!("{}: end", debugger.program_counter()),
+    };
+    format!(
+        "{} | [{}] after {} steps",
+        location,
+        format_state(debugger.state(), signed),
+        debugger.steps()
+    )
+}
+
+// executes one debugger command and returns what to print, or `None` to quit
+fn debug_command(
+    debugger: &mut Debugger,
+    line: &str,
+    signed: bool,
+    max_steps: usize,
+) -> Option<String> {
+    let words = line.split_whitespace().collect::<Vec<&str>>();
+    let number = |index: usize| words.get(index).map(|word| word.parse::<usize>());
+    // how often to step or go back
+    let count = number(1).and_then(Result::ok).unwrap_or(1);
+    let reply = match (words.first().copied(), number(1), number(2)) {
+        (None, _, _) => return Some(String::new()),
+        (Some("quit" | "q"), _, _) => return None,
+        (Some("help" | "h"), _, _) => DEBUG_HELP.to_string(),
+        (Some("step" | "s"), None | Some(Ok(_)), None) => {
+            let mut event = Event::Stepped;
+            for _ in 0..count {
+                event = debugger.step();
+                if event != Event::Stepped {
+                    break;
+                }
+            }
+            format!("{}\n{}", event, describe(debugger, signed))
+        }
+        (Some("back" | "b"), None | Some(Ok(_)), None) => {
+            let undone = (0..count).take_while(|_| debugger.back()).count();
+            format!("went back {} steps\n{}", undone, describe(debugger, signed))
+        }
+        (Some("continue" | "c"), None, None) => {
+            let event = debugger.resume(max_steps);
+            format!("{}\n{}", event, describe(debugger, signed))
+        }
+        (Some("break"), Some(Ok(index)), None) => {
+            debugger.add_breakpoint(index);
+            format!("breakpoint at {}", index)
+        }
+        (Some("delete"), Some(Ok(index)), None) => match debugger.remove_breakpoint(index) {
+            true => format!("removed breakpoint at {}", index),
+            false => format!("no breakpoint at {}", index),
+        },
+        (Some("watch"), Some(Ok(cell)), value) if value.as_ref().is_none_or(Result::is_ok) => {
+            let value = value.map(Result::unwrap);
+            debugger.watch(Watchpoint { cell, value });
+            format!("watching cell {}", cell)
+        }
+        (Some("unwatch"), Some(Ok(cell)), None) => match debugger.unwatch(cell) {
+            true => format!("stopped watching cell {}", cell),
+            false => format!("cell {} isn't watched", cell),
+        },
+        (Some("print" | "p"), None, None) => describe(debugger, signed),
+        (Some("list" | "l"), None, None) => debugger.listing(),
+        (Some("reset"), None, None) => {
+            debugger.reset();
+            describe(debugger, signed)
+        }
+        // anything else may be an instruction to add to the program
+        _ => {
+            let options = ParseOptions {
+                signed,
+                ..Default::default()
+            };
+            match parser::parse_instruction(line.trim(), &options) {
+                Ok(instruction) => run_typed(debugger, instruction, signed),
+                Err(_) => format!("Invalid command {} (try `help`)", line.trim()),
+            }
+        }
+    };
+    Some(reply)
+}
+
+// appends a typed instruction and, when the program had already run to its end, executes it
+fn run_typed(debugger: &mut Debugger, instruction: Instruction, signed: bool) -> String {
+    let halted = debugger.halted();
+    if let Err(error) = debugger.push(instruction) {
+        return format!("Can't add {}: {}", instruction, error);
+    }
+    match halted {
+        true => format!("{}\n{}", debugger.step(), describe(debugger, signed)),
+        false => format!(
+            "added {} as instruction {}",
+            instruction,
+            debugger.program().len() - 1
+        ),
+    }
+}
+
+fn debug(args: &Args) -> Result<bool, Failure> {
+    // without a program, start from an empty one and build it up by typing instructions
+    let (program, cells) = match args.positional.len() {
+        1 => (
+            Vec::new(),
+            args.value("cells")?.unwrap_or(DEFAULT_DEBUG_CELLS),
+        ),
+        _ => {
+            let assembly = load(args, &args.files(1)?[0])?;
+            let cells = cells_for(args, &assembly)?;
+            (assembly.program, cells)
+        }
+    };
+    let max_steps = args.value("max-steps")?.unwrap_or(DEFAULT_MAX_STEPS);
+    let mut debugger = Debugger::new(program, cells);
+
+    println!("{}", describe(&debugger, args.flag("signed")));
+    let stdin = std::io::stdin();
+    loop {
+        print!("(sop) ");
+        std::io::stdout().flush().ok();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => return Ok(true),
+            Ok(_) => {}
+            Err(error) => return Err(Failure::Input(error.to_string())),
+        }
+        match debug_command(&mut debugger, &line, args.flag("signed"), max_steps) {
+            Some(reply) if reply.is_empty() => {}
+            Some(reply) => println!("{}", reply),
+            None => return Ok(true),
+        }
+    }
+}
+
+fn compare(args: &Args) -> Result<bool, Failure> {
+    let files = args.files(2)?;
+    let left = load(args, &files[0])?;
+    let right = load(args, &files[1])?;
+    let cells = cells_for(args, &left)?.max(cells_for(args, &right)?);
+    let max_steps = args.value("max-steps")?.unwrap_or(DEFAULT_MAX_STEPS);
+
+    println!(
+        "{}",
+        debugger::side_by_side(&left.program, &right.program, cells, max_steps)
+    );
+    // same final states, and neither program faults
+    let [left, right] = [left, right].map(|assembly| {
+        let mut cpu = CPU::new(cells).with_max_steps(max_steps);
+        cpu.try_execute(&assembly.program).ok().map(|_| cpu.state)
+    });
+    Ok(left.is_some() && left == right)
+}
+
+fn batch(args: &Args) -> Result<bool, Failure> {
+    if args.positional.len() < 2 {
+        return Err(Failure::Usage(
+            "batch expects at least one file or directory".to_string(),
+        ));
+    }
+    // a directory stands for every program in it
+    let mut paths = Vec::new();
+    for path in &args.positional[1..] {
+        match Path::new(path).is_dir() {
+            true => paths.extend(
+                batch::programs_in(Path::new(path))
+                    .map_err(|error| Failure::Input(format!("{}: {}", path, error)))?
+                    .iter()
+                    .map(|path| path.display().to_string()),
+            ),
+            false => paths.push(path.clone()),
+        }
+    }
+    let jobs = paths
+        .iter()
+        .map(|path| BatchJob {
+            name: path.clone(),
+            assembly: load(args, path).map_err(|failure| failure.to_string()),
+        })
+        .collect::<Vec<BatchJob>>();
+    let options = BatchOptions {
+        limits: search_limits(args)?,
+        signed: args.flag("signed"),
+        time_budget: args.value("budget-ms")?.map(Duration::from_millis),
+        candidate_budget: args.value("budget-programs")?,
+    };
+
+    let report = batch::optimize_batch(&jobs, &options);
+    match args.flag("json") {
+        true => println!(
+            "{}",
+            report
+                .to_json()
+                .map_err(|error| Failure::Execution(error.to_string()))?
+        ),
+        false => println!("{}", report.to_markdown()),
+    }
+    Ok(report.errors() == 0)
+}
+
+fn dispatch(args: &Args) -> Result<bool, Failure> {
+    match args.positional.first().map(String::as_str) {
+        Some("run") => run(args),
+        Some("optimize") => optimize(args),
+        Some("verify") => verify(args),
+        Some("convert") => convert(args),
+        Some("analyze") => analyze(args),
+        Some("explain") => explain(args),
+        Some("debug") => debug(args),
+        Some("compare") => compare(args),
+        Some("batch") => batch(args),
+        Some("rules") => rules(args),
+        Some("smt") => smt(args),
+        Some(command) => Err(Failure::Usage(format!("Unknown command {}", command))),
+        None => Err(Failure::Usage("Missing command".to_string())),
+    }
+}
+
+fn main() -> ExitCode {
+    match Args::parse(std::env::args().skip(1)).and_then(|args| dispatch(&args)) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_NEGATIVE),
+        Err(failure) => {
+            eprintln!("{}", failure);
+            ExitCode::from(failure.code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use superoptimusprime::config::MAX_COVERED_VALUE;
+
+    fn args(line: &str) -> Args {
+        Args::parse(line.split_whitespace().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn parses_arguments() {
+        let parsed = args("optimize prog.asm --json --backend simple --max-length 3");
+        assert_eq!(parsed.positional, vec!["optimize", "prog.asm"]);
+        assert!(parsed.flag("json"));
+        assert_eq!(
+            parsed.value::<Backend>("backend"),
+            Ok(Some(Backend::Simple))
+        );
+        assert_eq!(parsed.value::<usize>("max-length"), Ok(Some(3)));
+        assert_eq!(parsed.value::<usize>("cells"), Ok(None));
+
+        assert_eq!(args("run a b").files(1).unwrap_err().code(), EXIT_USAGE);
+        assert_eq!(
+            args("run --cells x")
+                .value::<usize>("cells")
+                .unwrap_err()
+                .code(),
+            EXIT_USAGE
+        );
+        assert!(Args::parse(vec!["--cells".to_string()]).is_err());
+    }
+
+    #[test]
+    fn picks_formats() {
+        assert_eq!(format_of("a.bin", None), Format::Binary);
+        assert_eq!(format_of("dir.v2/a.json", None), Format::Json);
+        assert_eq!(format_of("a.asm", None), Format::Assembly);
+        assert_eq!(format_of("-", Some(Format::Binary)), Format::Binary);
+    }
+
+    #[test]
+    fn search_defaults_cover_the_input() {
+        let assembly = parser::parse_assembly("LOAD 3\nSWAP 0, 1\nLOAD 3").unwrap();
+        let cells = cells_for(&args("optimize"), &assembly).unwrap();
+        let config = search_config(&args("optimize"), &assembly, cells).unwrap();
+        assert_eq!(cells, 2);
+        assert_eq!(config.max_instructions_length, 3);
+        assert_eq!(config.max_value, 4);
+        assert_eq!(
+            config.instruction_set,
+            InstructionSet::new(&["LOAD", "SWAP"])
+        );
+
+        // large immediates don't put every value below them in the alphabet
+        let large = parser::parse_assembly("LOAD 100000000").unwrap();
+        let config = search_config(&args("optimize"), &large, 1).unwrap();
+        assert_eq!(config.max_value, MAX_COVERED_VALUE);
+        let config = search_config(&args("optimize --max-value 1000"), &large, 1).unwrap();
+        assert_eq!(config.max_value, 1000);
+
+        // the input answers when it lies outside the values searched
+        let mut found = None;
+        fall_back_to_input(&mut found, &large);
+        assert_eq!(found, Some(large.program.clone()));
+        let mut found = Some(parser::parse("LOAD 1").unwrap());
+        fall_back_to_input(&mut found, &large);
+        assert_eq!(found, Some(large.program.clone()));
+        let mut found = None;
+        fall_back_to_input(&mut found, &parser::parse_assembly(".target 5").unwrap());
+        assert_eq!(found, None);
+
+        let unknown = search_config(&args("optimize --ops LOAD,FOO"), &assembly, cells);
+        assert_eq!(
+            unknown.unwrap_err(),
+            Failure::Usage("Unknown operation FOO".to_string())
+        );
+    }
+
+    #[test]
+    fn reads_output_cells() {
+        assert_eq!(outputs_for(&args("analyze"), 3), Ok(vec![0, 1, 2]));
+        assert_eq!(
+            outputs_for(&args("analyze --outputs 2,0"), 3),
+            Ok(vec![2, 0])
+        );
+        assert_eq!(
+            outputs_for(&args("analyze --outputs 3"), 3),
+            Err(Failure::Usage("Output cell 3 is out of bounds".to_string()))
+        );
+        assert!(outputs_for(&args("analyze --outputs a"), 3).is_err());
+    }
+
+    #[test]
+    fn runs_debugger_commands() {
+        let program = parser::parse("LOAD 3\nSWAP 0, 1\nINC 0").unwrap();
+        let mut debugger = Debugger::new(program, 2);
+        let mut command = |line: &str| debug_command(&mut debugger, line, false, 100);
+
+        assert_eq!(
+            command("step 2"),
+            Some("stepped\n2: INC 0 | [0, 3] after 2 steps".to_string())
+        );
+        assert_eq!(
+            command("b"),
+            Some("went back 1 steps\n1: SWAP 0, 1 | [3, 0] after 1 steps".to_string())
+        );
+        assert_eq!(command("break 2"), Some("breakpoint at 2".to_string()));
+        assert_eq!(
+            command("c"),
+            Some("breakpoint at 2\n2: INC 0 | [0, 3] after 2 steps".to_string())
+        );
+        assert_eq!(command("watch 0 1"), Some("watching cell 0".to_string()));
+        assert_eq!(
+            command("continue"),
+            Some("watchpoint: cell 0 is now 1\n3: end | [1, 3] after 3 steps".to_string())
+        );
+        assert_eq!(
+            command("watch x"),
+            Some("Invalid command watch x (try `help`)".to_string())
+        );
+        assert_eq!(
+            command("ADD 1, 0"),
+            Some("halted\n4: end | [1, 4] after 4 steps".to_string())
+        );
+        assert_eq!(
+            command("INC 2"),
+            Some("Can't add INC 2: Instruction 4 accesses out of bounds cell 2".to_string())
+        );
+        command("reset");
+        assert_eq!(
+            command("DEC 0"),
+            Some("added DEC 0 as instruction 4".to_string())
+        );
+        assert_eq!(command(""), Some(String::new()));
+        assert_eq!(command("quit"), None);
+    }
+}
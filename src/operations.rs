@@ -1,19 +1,68 @@
-pub fn load(state: &mut Vec<usize>, value: usize) {
+pub fn load(state: &mut [usize], value: usize) {
     state[0] = value;
 }
 
-pub fn swap(state: &mut Vec<usize>, memory1: usize, memory2: usize) {
-    let temp = state[memory1];
-    state[memory1] = state[memory2];
-    state[memory2] = temp;
+pub fn load_to(state: &mut [usize], memory: usize, value: usize) {
+    state[memory] = value;
+}
+
+pub fn swap(state: &mut [usize], memory1: usize, memory2: usize) {
+    state.swap(memory1, memory2);
 }
 
-pub fn xor(state: &mut Vec<usize>, memory1: usize, memory2: usize) {
+pub fn xor(state: &mut [usize], memory1: usize, memory2: usize) {
     state[memory1] ^= state[memory2];
 }
 
-pub fn inc(state: &mut Vec<usize>, memory: usize) {
-    state[memory] += 1;
+pub fn inc(state: &mut [usize], memory: usize) {
+    state[memory] = state[memory].wrapping_add(1);
+}
+
+pub fn add(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] = state[memory1].wrapping_add(state[memory2]);
+}
+
+pub fn sub(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] = state[memory1].wrapping_sub(state[memory2]);
+}
+
+pub fn and(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] &= state[memory2];
+}
+
+pub fn or(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] |= state[memory2];
+}
+
+pub fn not(state: &mut [usize], memory: usize) {
+    state[memory] = !state[memory];
+}
+
+pub fn dec(state: &mut [usize], memory: usize) {
+    state[memory] = state[memory].wrapping_sub(1);
+}
+
+// shifting by the word width or more clears the cell
+pub fn shl(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] = u32::try_from(state[memory2])
+        .ok()
+        .and_then(|amount| state[memory1].checked_shl(amount))
+        .unwrap_or(0);
+}
+
+pub fn shr(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] = u32::try_from(state[memory2])
+        .ok()
+        .and_then(|amount| state[memory1].checked_shr(amount))
+        .unwrap_or(0);
+}
+
+pub fn mov(state: &mut [usize], memory1: usize, memory2: usize) {
+    state[memory1] = state[memory2];
+}
+
+pub fn neg(state: &mut [usize], memory: usize) {
+    state[memory] = state[memory].wrapping_neg();
 }
 
 #[cfg(test)]
@@ -27,6 +76,13 @@ mod tests {
         assert_eq!(state, vec![1, 0, 0, 0]);
     }
 
+    #[test]
+    fn can_load_to() {
+        let mut state = vec![0; 4];
+        load_to(&mut state, 2, 5);
+        assert_eq!(state, vec![0, 0, 5, 0]);
+    }
+
     #[test]
     fn can_swap() {
         let mut state = vec![0, 1, 0, 0, 0];
@@ -47,4 +103,80 @@ mod tests {
         inc(&mut state, 3);
         assert_eq!(state, vec![0, 0, 0, 1]);
     }
+
+    #[test]
+    fn can_add() {
+        let mut state = vec![2, 3, usize::MAX];
+        add(&mut state, 0, 1);
+        add(&mut state, 2, 1);
+        assert_eq!(state, vec![5, 3, 2]);
+    }
+
+    #[test]
+    fn can_sub() {
+        let mut state = vec![5, 3, 0];
+        sub(&mut state, 0, 1);
+        sub(&mut state, 2, 1);
+        assert_eq!(state, vec![2, 3, usize::MAX - 2]);
+    }
+
+    #[test]
+    fn can_and() {
+        let mut state = vec![0b1100, 0b1010];
+        and(&mut state, 0, 1);
+        assert_eq!(state, vec![0b1000, 0b1010]);
+    }
+
+    #[test]
+    fn can_or() {
+        let mut state = vec![0b1100, 0b1010];
+        or(&mut state, 0, 1);
+        assert_eq!(state, vec![0b1110, 0b1010]);
+    }
+
+    #[test]
+    fn can_not() {
+        let mut state = vec![0, 1];
+        not(&mut state, 0);
+        assert_eq!(state, vec![usize::MAX, 1]);
+    }
+
+    #[test]
+    fn can_dec() {
+        let mut state = vec![0, 2];
+        dec(&mut state, 0);
+        dec(&mut state, 1);
+        assert_eq!(state, vec![usize::MAX, 1]);
+    }
+
+    #[test]
+    fn can_shl() {
+        let mut state = vec![3, 2, 1, 64];
+        shl(&mut state, 0, 1);
+        shl(&mut state, 2, 3);
+        assert_eq!(state, vec![12, 2, 0, 64]);
+    }
+
+    #[test]
+    fn can_shr() {
+        let mut state = vec![12, 2, 1, 64];
+        shr(&mut state, 0, 1);
+        shr(&mut state, 2, 3);
+        assert_eq!(state, vec![3, 2, 0, 64]);
+    }
+
+    #[test]
+    fn can_mov() {
+        let mut state = vec![0, 7];
+        mov(&mut state, 0, 1);
+        assert_eq!(state, vec![7, 7]);
+    }
+
+    #[test]
+    fn can_neg() {
+        let mut state = vec![1, 0];
+        neg(&mut state, 0);
+        neg(&mut state, 1);
+        assert_eq!(state, vec![usize::MAX, 0]);
+    }
 }
@@ -1,9 +1,18 @@
 use regex::Regex;
+use std::collections::HashMap;
 use std::fmt;
 use std::num::ParseIntError;
+use std::ops::Range;
 use std::str::FromStr;
+use std::sync::OnceLock;
 
 use crate::cpu::Instruction;
+use crate::isa::InstructionSemantics;
+
+// a decimal, `0x` hex or `0b` binary number, a character such as `'A'` or `'\n'`, or a name
+const OPERAND_PATTERN: &str =
+    r"-?(?:0[xX][0-9A-Fa-f]+|0[bB][01]+|[0-9]+)|'(?:[^'\\]|\\[nrt0'\\])'|[A-Za-z_]\w*";
+const LABEL_PATTERN: &str = r"^\s*([A-Za-z_]\w*):(.*)$";
 
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
@@ -11,68 +20,632 @@ pub enum ParseError {
     NoArgs,
     Regex(regex::Error),
     ArgParse(ParseIntError),
+    UnknownLabel(String),
+    UnexpectedToken(String),
+    UnknownDirective(String),
+    DuplicateSymbol(String),
+    Arity {
+        operation: String,
+        expected: usize,
+        found: usize,
+    },
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid operation")
+        match self {
+            ParseError::InvalidOp => write!(f, "Invalid operation"),
+            ParseError::NoArgs => write!(f, "Expected an operation followed by its operands"),
+            ParseError::Regex(error) => write!(f, "{}", error),
+            ParseError::ArgParse(error) => write!(f, "Invalid operand: {}", error),
+            ParseError::UnknownLabel(label) => write!(f, "Unknown label {}", label),
+            ParseError::UnexpectedToken(token) => write!(f, "Unexpected token {}", token),
+            ParseError::UnknownDirective(name) => write!(f, "Unknown directive .{}", name),
+            ParseError::DuplicateSymbol(name) => write!(f, "{} is already defined", name),
+            ParseError::Arity {
+                operation,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} expects {} operands, got {}",
+                operation, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// a `ParseError` located in the source; lines and columns count from 1
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+    pub token: String,
+    pub error: ParseError,
+}
+
+impl Diagnostic {
+    fn new(line: usize, text: &str, span: Range<usize>, error: ParseError) -> Diagnostic {
+        Diagnostic {
+            line,
+            column: text[..span.start].chars().count() + 1,
+            length: text[span.clone()].chars().count(),
+            token: text[span].to_string(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (line, column) = (self.line, self.column);
+        match &self.error {
+            ParseError::InvalidOp => write!(
+                f,
+                "Invalid operation {} at line {}:{}",
+                self.token, line, column
+            ),
+            ParseError::ArgParse(error) => write!(
+                f,
+                "Invalid operand {} at line {}:{} ({})",
+                self.token, line, column, error
+            ),
+            error => write!(f, "{} at line {}:{}", error, line, column),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseOptions {
+    // accept negative immediates, stored as their two's complement
+    pub signed: bool,
+    // the original unanchored grammar: whatever follows the operands is ignored, as are
+    // operands beyond the ones the operation takes
+    pub lenient: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputOptions {
+    // print immediates as two's complement signed values
+    pub signed: bool,
+    // print immediates as `0x` hex rather than decimal
+    pub hex: bool,
+    // print mnemonics (and hex digits) in lowercase
+    pub lowercase: bool,
+}
+
+// position of the operand holding an immediate value rather than a cell or jump target
+fn immediate_operand(op_str: &str) -> Option<usize> {
+    match op_str {
+        "LOAD" => Some(0),
+        "LOADTO" => Some(1),
+        _ => None,
+    }
+}
+
+fn arity(op_str: &str) -> Option<usize> {
+    match op_str {
+        "LOAD" | "INC" | "NOT" | "DEC" | "NEG" | "JMP" => Some(1),
+        "SWAP" | "XOR" | "ADD" | "SUB" | "AND" | "OR" | "SHL" | "SHR" | "MOV" | "JZ" | "JNZ"
+        | "LOADTO" => Some(2),
+        _ => None,
     }
 }
 
+// the byte range of `line` without surrounding whitespace
+fn trimmed_span(line: &str) -> Range<usize> {
+    let start = line.len() - line.trim_start().len();
+    start..line.trim_end().len().max(start)
+}
+
 impl FromStr for Instruction {
     type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(r"(\w+)\s+([-\d]+)(?:,\s*([-\d]+)(?:,\s*([-\d]+))?)?")
-            .map_err(|e| ParseError::Regex(e))?;
+        parse_instruction(s, &ParseOptions::default())
+    }
+}
 
-        let caps = re.captures(s);
-        if caps.is_none() {
-            return Err(ParseError::NoArgs);
-        }
-        let caps = caps.unwrap();
-        let op_str = &caps[1];
-
-        let args: Vec<usize> = caps
-            .iter()
-            .skip(2)
-            .filter_map(|m| m)
-            .map(|m| {
-                m.as_str()
-                    .parse::<usize>()
-                    .map_err(|e| ParseError::ArgParse(e))
-            })
-            .collect::<Result<Vec<usize>, ParseError>>()?;
-
-        match op_str {
-            "LOAD" => Ok(Instruction::Load(args[0])),
-            "SWAP" => Ok(Instruction::Swap(args[0], args[1])),
-            "XOR" => Ok(Instruction::Xor(args[0], args[1])),
-            "INC" => Ok(Instruction::Inc(args[0])),
-            _ => Err(ParseError::InvalidOp),
+pub fn parse_instruction(s: &str, options: &ParseOptions) -> Result<Instruction, ParseError> {
+    let grammar = Grammar::get()?;
+    parse_located(s, grammar, &HashMap::new(), options).map_err(|(error, _)| error)
+}
+
+struct Grammar {
+    lenient: Regex,
+    operand: Regex,
+    label: Regex,
+    // names in operand position, and the character literals that may hold what looks like one
+    name: Regex,
+}
+
+impl Grammar {
+    fn new() -> Result<Grammar, regex::Error> {
+        Ok(Grammar {
+            lenient: Regex::new(&format!(
+                r"(\w+)\s+({0})(?:,\s*({0})(?:,\s*({0}))?)?",
+                OPERAND_PATTERN
+            ))?,
+            operand: Regex::new(&format!("^(?:{})", OPERAND_PATTERN))?,
+            label: Regex::new(LABEL_PATTERN)?,
+            name: Regex::new(r"'(?:[^'\\]|\\.)'|\b[A-Za-z_]\w*")?,
+        })
+    }
+
+    // compiled on first use and shared by every parse after it
+    fn get() -> Result<&'static Grammar, ParseError> {
+        static GRAMMAR: OnceLock<Result<Grammar, regex::Error>> = OnceLock::new();
+        GRAMMAR
+            .get_or_init(Grammar::new)
+            .as_ref()
+            .map_err(|error| ParseError::Regex(error.clone()))
+    }
+}
+
+// a token of an instruction and its byte range in the line
+type Token<'a> = (&'a str, Range<usize>);
+
+type Located<T> = Result<T, (ParseError, Range<usize>)>;
+
+fn tokenize_lenient<'a>(line: &'a str, grammar: &Grammar) -> Located<(Token<'a>, Vec<Token<'a>>)> {
+    let caps = grammar
+        .lenient
+        .captures(line)
+        .ok_or_else(|| (ParseError::NoArgs, trimmed_span(line)))?;
+    let op = caps.get(1).unwrap();
+    let operands = caps
+        .iter()
+        .skip(2)
+        .flatten()
+        .map(|m| (m.as_str(), m.range()))
+        .collect();
+    Ok(((op.as_str(), op.range()), operands))
+}
+
+// `MNEMONIC operand, operand, ...` and nothing else, operands being numbers or labels
+fn tokenize_strict<'a>(line: &'a str, grammar: &Grammar) -> Located<(Token<'a>, Vec<Token<'a>>)> {
+    let skip_whitespace =
+        |position: usize| position + line[position..].len() - line[position..].trim_start().len();
+    // the run of characters at `position` up to the next separator, for error reporting
+    let unexpected = |position: usize| {
+        let rest = &line[position..];
+        let length = rest
+            .find(|c: char| c.is_whitespace() || c == ',')
+            .unwrap_or(rest.len())
+            .max(rest.chars().next().map_or(0, char::len_utf8));
+        (
+            ParseError::UnexpectedToken(rest[..length].to_string()),
+            position..position + length,
+        )
+    };
+    let token = |position: usize| {
+        grammar
+            .operand
+            .find(&line[position..])
+            .map(|m| (m.as_str(), position..position + m.end()))
+    };
+
+    let start = skip_whitespace(0);
+    if start == line.len() {
+        return Err((ParseError::NoArgs, start..start));
+    }
+    let op = match token(start) {
+        Some((text, span)) if text.starts_with(|c: char| c.is_ascii_alphabetic()) => (text, span),
+        _ => return Err(unexpected(start)),
+    };
+
+    let mut operands = Vec::new();
+    let mut position = op.1.end;
+    if skip_whitespace(position) == line.len() {
+        return Ok((op, operands));
+    }
+    if position == skip_whitespace(position) {
+        return Err(unexpected(position));
+    }
+
+    loop {
+        position = skip_whitespace(position);
+        let operand = token(position).ok_or_else(|| unexpected(position))?;
+        position = skip_whitespace(operand.1.end);
+        operands.push(operand);
+
+        match line[position..].chars().next() {
+            None => return Ok((op, operands)),
+            Some(',') if skip_whitespace(position + 1) < line.len() => position += 1,
+            _ => return Err(unexpected(position)),
         }
     }
 }
 
+// the code point of a character literal; escapes are restricted by the grammar
+fn char_literal(text: &str) -> Option<usize> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let c = match inner {
+        "\\n" => '\n',
+        "\\r" => '\r',
+        "\\t" => '\t',
+        "\\0" => '\0',
+        _ => inner.chars().last()?,
+    };
+    Some(c as usize)
+}
+
+fn parse_value(text: &str, signed: bool) -> Result<usize, ParseIntError> {
+    if let Some(value) = char_literal(text) {
+        return Ok(value);
+    }
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", text),
+    };
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    let number = format!("{}{}", sign, digits);
+    match signed {
+        true => isize::from_str_radix(&number, radix).map(|value| value as usize),
+        false => usize::from_str_radix(&number, radix),
+    }
+}
+
+// a number, or the name of a label or `.equ` constant
+fn operand_value(
+    (text, span): &Token,
+    symbols: &HashMap<String, usize>,
+    signed: bool,
+) -> Located<usize> {
+    if text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return symbols
+            .get(*text)
+            .copied()
+            .ok_or_else(|| (ParseError::UnknownLabel(text.to_string()), span.clone()));
+    }
+    parse_value(text, signed).map_err(|error| (ParseError::ArgParse(error), span.clone()))
+}
+
+// parses a single instruction, locating errors by the byte range of the offending token
+fn parse_located(
+    line: &str,
+    grammar: &Grammar,
+    symbols: &HashMap<String, usize>,
+    options: &ParseOptions,
+) -> Located<Instruction> {
+    let ((op_text, op_span), operands) = match options.lenient {
+        true => tokenize_lenient(line, grammar)?,
+        false => tokenize_strict(line, grammar)?,
+    };
+    let op_upper = op_text.to_ascii_uppercase();
+    let op_str = op_upper.as_str();
+    let expected = arity(op_str).ok_or((ParseError::InvalidOp, op_span.clone()))?;
+
+    if operands.is_empty() {
+        return Err((ParseError::NoArgs, op_span));
+    }
+    if operands.len() < expected || (operands.len() > expected && !options.lenient) {
+        let error = ParseError::Arity {
+            operation: op_str.to_string(),
+            expected,
+            found: operands.len(),
+        };
+        return Err((error, op_span.start..operands[operands.len() - 1].1.end));
+    }
+
+    let immediate = immediate_operand(op_str).filter(|_| options.signed);
+    let args = operands
+        .iter()
+        .enumerate()
+        .map(|(index, token)| operand_value(token, symbols, immediate == Some(index)))
+        .collect::<Located<Vec<usize>>>()?;
+
+    match op_str {
+        "LOAD" => Ok(Instruction::Load(args[0])),
+        "SWAP" => Ok(Instruction::Swap(args[0], args[1])),
+        "XOR" => Ok(Instruction::Xor(args[0], args[1])),
+        "INC" => Ok(Instruction::Inc(args[0])),
+        "ADD" => Ok(Instruction::Add(args[0], args[1])),
+        "SUB" => Ok(Instruction::Sub(args[0], args[1])),
+        "AND" => Ok(Instruction::And(args[0], args[1])),
+        "OR" => Ok(Instruction::Or(args[0], args[1])),
+        "NOT" => Ok(Instruction::Not(args[0])),
+        "DEC" => Ok(Instruction::Dec(args[0])),
+        "SHL" => Ok(Instruction::Shl(args[0], args[1])),
+        "SHR" => Ok(Instruction::Shr(args[0], args[1])),
+        "MOV" => Ok(Instruction::Mov(args[0], args[1])),
+        "NEG" => Ok(Instruction::Neg(args[0])),
+        "JMP" => Ok(Instruction::Jmp(args[0])),
+        "JZ" => Ok(Instruction::Jz(args[0], args[1])),
+        "JNZ" => Ok(Instruction::Jnz(args[0], args[1])),
+        "LOADTO" => Ok(Instruction::LoadTo(args[0], args[1])),
+        _ => Err((ParseError::InvalidOp, op_span)),
+    }
+}
+
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Instruction::Load(val) => write!(f, "LOAD {}", val),
-            Instruction::Swap(mem1, mem2) => write!(f, "SWAP {}, {}", mem1, mem2),
-            Instruction::Xor(mem1, mem2) => write!(f, "XOR {}, {}", mem1, mem2),
-            Instruction::Inc(mem) => write!(f, "INC {}", mem),
+        write!(f, "{}", format_instruction(self, &OutputOptions::default()))
+    }
+}
+
+fn format_immediate(value: usize, options: &OutputOptions) -> String {
+    let (sign, magnitude) = match options.signed && (value as isize) < 0 {
+        true => ("-", (value as isize).unsigned_abs()),
+        false => ("", value),
+    };
+    match (options.hex, options.lowercase) {
+        (false, _) => format!("{}{}", sign, magnitude),
+        (true, false) => format!("{}{:#X}", sign, magnitude),
+        (true, true) => format!("{}{:#x}", sign, magnitude),
+    }
+}
+
+pub fn format_instruction(instruction: &Instruction, options: &OutputOptions) -> String {
+    let operation = instruction.operation();
+    let immediate = immediate_operand(&operation);
+    let arguments = instruction
+        .arguments()
+        .iter()
+        .enumerate()
+        .map(|(index, arg)| match immediate {
+            Some(position) if position == index => format_immediate(*arg, options),
+            _ => arg.to_string(),
+        })
+        .collect::<Vec<String>>();
+    let operation = match options.lowercase {
+        true => operation.to_ascii_lowercase(),
+        false => operation,
+    };
+    format!("{} {}", operation, arguments.join(", "))
+}
+
+// replaces label and constant names in operand position with their values
+fn resolve_labels(line: &str, symbols: &HashMap<String, usize>) -> Result<String, ParseError> {
+    let trimmed = line.trim_start();
+    let op_str = trimmed.split_whitespace().next().unwrap_or("");
+
+    let re = &Grammar::get()?.name;
+    let operands = &trimmed[op_str.len()..];
+    let mut resolved = String::from(op_str);
+    let mut last = 0;
+    // character literals are matched only to be skipped over
+    for label in re
+        .find_iter(operands)
+        .filter(|m| !m.as_str().starts_with('\''))
+    {
+        let target = symbols
+            .get(label.as_str())
+            .ok_or_else(|| ParseError::UnknownLabel(label.as_str().to_string()))?;
+        resolved.push_str(&operands[last..label.start()]);
+        resolved.push_str(&target.to_string());
+        last = label.end();
+    }
+    resolved.push_str(&operands[last..]);
+    Ok(resolved)
+}
+
+// a program together with the search configuration its source declares
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly<I = Instruction> {
+    pub program: Vec<I>,
+    // from `.cells N`
+    pub cells: Option<usize>,
+    // from `.target value, value, ...`
+    pub target: Option<Vec<usize>>,
+}
+
+// a piece of source text found `offset` bytes into line `number`
+struct SourceLine<'a> {
+    number: usize,
+    source: &'a str,
+    offset: usize,
+    text: &'a str,
+}
+
+impl<'a> SourceLine<'a> {
+    fn diagnostic(&self, span: Range<usize>, error: ParseError) -> Diagnostic {
+        let span = span.start + self.offset..span.end + self.offset;
+        Diagnostic::new(self.number, self.source, span, error)
+    }
+
+    fn slice(&self, range: Range<usize>) -> SourceLine<'a> {
+        SourceLine {
+            number: self.number,
+            source: self.source,
+            offset: self.offset + range.start,
+            text: &self.text[range],
+        }
+    }
+}
+
+// everything before a `;` or `#` comment, which may not start inside a character literal
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '\'' => quoted = !quoted,
+            ';' | '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn define(symbols: &mut HashMap<String, usize>, (name, span): &Token, value: usize) -> Located<()> {
+    if symbols.contains_key(*name) {
+        return Err((ParseError::DuplicateSymbol(name.to_string()), span.clone()));
+    }
+    symbols.insert(name.to_string(), value);
+    Ok(())
+}
+
+// applies a `.cells`, `.target` or `.equ` line (without its leading dot) to `assembly`
+fn parse_directive<I>(
+    text: &str,
+    grammar: &Grammar,
+    symbols: &mut HashMap<String, usize>,
+    assembly: &mut Assembly<I>,
+    signed: bool,
+) -> Located<()> {
+    let ((name, name_span), operands) = tokenize_strict(text, grammar)?;
+    let name = name.to_ascii_lowercase();
+    let name = name.as_str();
+    let expected = match name {
+        "cells" => 1,
+        "equ" => 2,
+        "target" => operands.len().max(1),
+        _ => return Err((ParseError::UnknownDirective(name.to_string()), name_span)),
+    };
+    if operands.len() != expected {
+        let end = operands.last().map_or(name_span.end, |(_, span)| span.end);
+        let error = ParseError::Arity {
+            operation: format!(".{}", name),
+            expected,
+            found: operands.len(),
+        };
+        return Err((error, name_span.start..end));
+    }
+
+    match name {
+        "cells" => assembly.cells = Some(operand_value(&operands[0], symbols, false)?),
+        "equ" => {
+            let value = operand_value(&operands[1], symbols, signed)?;
+            define(symbols, &operands[0], value)?;
+        }
+        _ => {
+            let values = operands
+                .iter()
+                .map(|token| operand_value(token, symbols, signed))
+                .collect::<Located<Vec<usize>>>()?;
+            assembly.target = Some(values);
         }
     }
+    Ok(())
+}
+
+// runs `parse_line` over every instruction of `source` once comments, directives and labels
+// are taken care of, gathering all failures
+fn parse_source<I>(
+    source: &str,
+    signed: bool,
+    parse_line: impl Fn(&SourceLine, &HashMap<String, usize>) -> Result<I, Diagnostic>,
+) -> Result<Assembly<I>, Vec<Diagnostic>> {
+    let grammar = Grammar::get().map_err(|error| vec![Diagnostic::new(0, "", 0..0, error)])?;
+
+    let mut assembly = Assembly {
+        program: Vec::new(),
+        cells: None,
+        target: None,
+    };
+    let mut symbols = HashMap::new();
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, source_line) in source.lines().enumerate() {
+        let mut line = SourceLine {
+            number: index + 1,
+            source: source_line,
+            offset: 0,
+            text: strip_comment(source_line),
+        };
+
+        // a `name:` prefix labels the next instruction, which may follow on the same line
+        if let Some(caps) = grammar.label.captures(line.text) {
+            let label = caps.get(1).unwrap();
+            if let Err((error, span)) =
+                define(&mut symbols, &(label.as_str(), label.range()), lines.len())
+            {
+                errors.push(line.diagnostic(span, error));
+            }
+            line = line.slice(caps.get(2).unwrap().range());
+        }
+
+        let trimmed = line.text.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with('.') {
+            let directive = line.slice(line.text.len() - trimmed.len() + 1..line.text.len());
+            let result =
+                parse_directive(directive.text, grammar, &mut symbols, &mut assembly, signed);
+            if let Err((error, span)) = result {
+                errors.push(directive.diagnostic(span, error));
+            }
+            continue;
+        }
+        lines.push(line);
+    }
+
+    for line in &lines {
+        match parse_line(line, &symbols) {
+            Ok(instruction) => assembly.program.push(instruction),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    errors.sort_by_key(|error| (error.line, error.column));
+    match errors.is_empty() {
+        true => Ok(assembly),
+        false => Err(errors),
+    }
+}
+
+pub fn parse(assembly: &str) -> Result<Vec<Instruction>, Diagnostic> {
+    parse_with(assembly, &ParseOptions::default())
+}
+
+pub fn parse_with(assembly: &str, options: &ParseOptions) -> Result<Vec<Instruction>, Diagnostic> {
+    parse_all_with(assembly, options).map_err(|mut errors| errors.remove(0))
+}
+
+// like `parse`, but reports every invalid line instead of only the first
+pub fn parse_all(assembly: &str) -> Result<Vec<Instruction>, Vec<Diagnostic>> {
+    parse_all_with(assembly, &ParseOptions::default())
+}
+
+pub fn parse_all_with(
+    assembly: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Instruction>, Vec<Diagnostic>> {
+    parse_assembly_with(assembly, options).map(|assembly| assembly.program)
+}
+
+// parses a program and the `.cells` and `.target` directives it carries
+pub fn parse_assembly(source: &str) -> Result<Assembly, Vec<Diagnostic>> {
+    parse_assembly_with(source, &ParseOptions::default())
 }
 
-pub fn parse(assembly: &str) -> Result<Vec<Instruction>, ParseError> {
-    assembly
-        .lines()
-        .map(|line| line.parse::<Instruction>())
-        .collect::<Result<Vec<Instruction>, ParseError>>()
+pub fn parse_assembly_with(
+    source: &str,
+    options: &ParseOptions,
+) -> Result<Assembly, Vec<Diagnostic>> {
+    let grammar = Grammar::get().map_err(|error| vec![Diagnostic::new(0, "", 0..0, error)])?;
+    parse_source(source, options.signed, |line, symbols| {
+        parse_located(line.text, grammar, symbols, options)
+            .map_err(|(error, span)| line.diagnostic(span, error))
+    })
 }
 
-pub fn output(program: &Vec<Instruction>) -> String {
+// parses any instruction set through its `FromStr`, so errors are located by line only
+pub fn parse_program<I: InstructionSemantics>(assembly: &str) -> Result<Vec<I>, Diagnostic> {
+    parse_source(assembly, false, |line, symbols| {
+        resolve_labels(line.text, symbols)
+            .and_then(|resolved| resolved.parse::<I>())
+            .map_err(|error| line.diagnostic(trimmed_span(line.text), error))
+    })
+    .map(|assembly| assembly.program)
+    .map_err(|mut errors| errors.remove(0))
+}
+
+pub fn output<I: InstructionSemantics>(program: &[I]) -> String {
     program
         .iter()
         .map(|op| op.to_string())
@@ -80,9 +653,19 @@ pub fn output(program: &Vec<Instruction>) -> String {
         .join("\n")
 }
 
+pub fn output_with(program: &[Instruction], options: &OutputOptions) -> String {
+    program
+        .iter()
+        .map(|op| format_instruction(op, options))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::strategies;
+    use proptest::prelude::*;
 
     #[test]
     fn from_str_valid_operation() {
@@ -113,7 +696,7 @@ mod tests {
         let result = parse(assembly);
         assert!(result.is_ok());
         let parsed = result.unwrap();
-        let expected = vec![
+        let expected = [
             Instruction::Load(0),
             Instruction::Swap(1, 2),
             Instruction::Xor(3, 4),
@@ -128,7 +711,8 @@ mod tests {
         let result = parse(assembly);
         assert!(result.is_err());
         let error = result.unwrap_err();
-        assert_eq!(error, ParseError::InvalidOp);
+        assert_eq!(error.error, ParseError::InvalidOp);
+        assert_eq!(error.line, 4);
     }
 
     #[test]
@@ -154,4 +738,352 @@ mod tests {
         let expected = "LOAD 0\nSWAP 1, 2\nXOR 3, 4\nINC 5";
         assert_eq!(output, expected);
     }
+
+    #[test]
+    fn can_parse_and_output_extended_operations() {
+        let assembly = "ADD 0, 1\nSUB 1, 2\nAND 2, 3\nOR 3, 4\nNOT 4\nDEC 5\nSHL 0, 1\nSHR 1, 0\nMOV 2, 0\nNEG 3\nLOADTO 2, 9";
+        let parsed = parse(assembly).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                Instruction::Add(0, 1),
+                Instruction::Sub(1, 2),
+                Instruction::And(2, 3),
+                Instruction::Or(3, 4),
+                Instruction::Not(4),
+                Instruction::Dec(5),
+                Instruction::Shl(0, 1),
+                Instruction::Shr(1, 0),
+                Instruction::Mov(2, 0),
+                Instruction::Neg(3),
+                Instruction::LoadTo(2, 9),
+            ]
+        );
+        assert_eq!(output(&parsed), assembly);
+    }
+
+    #[test]
+    fn can_parse_labels() {
+        let assembly = "LOAD 3\nloop: INC 1\nINC 1\nDEC 0\nJNZ 0, loop\nJZ 0, end\nJMP 0\nend:";
+        let parsed = parse(assembly).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                Instruction::Load(3),
+                Instruction::Inc(1),
+                Instruction::Inc(1),
+                Instruction::Dec(0),
+                Instruction::Jnz(0, 1),
+                Instruction::Jz(0, 7),
+                Instruction::Jmp(0),
+            ]
+        );
+        assert_eq!(
+            output(&parsed),
+            "LOAD 3\nINC 1\nINC 1\nDEC 0\nJNZ 0, 1\nJZ 0, 7\nJMP 0"
+        );
+    }
+
+    #[test]
+    fn cant_parse_unknown_label() {
+        let result = parse("JMP nowhere");
+        assert_eq!(
+            result.unwrap_err().error,
+            ParseError::UnknownLabel(String::from("nowhere"))
+        );
+    }
+
+    #[test]
+    fn can_parse_and_output_signed_immediates() {
+        let assembly = "LOAD -3\nLOADTO 2, -1\nINC 1";
+        assert!(matches!(
+            parse(assembly).map_err(|e| e.error),
+            Err(ParseError::ArgParse(_))
+        ));
+
+        let program = parse_with(
+            assembly,
+            &ParseOptions {
+                signed: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Instruction::Load(-3isize as usize),
+                Instruction::LoadTo(2, usize::MAX),
+                Instruction::Inc(1),
+            ]
+        );
+        assert_eq!(
+            output_with(
+                &program,
+                &OutputOptions {
+                    signed: true,
+                    ..Default::default()
+                }
+            ),
+            assembly
+        );
+        assert_eq!(
+            output(&program),
+            format!("LOAD {}\nLOADTO 2, {}\nINC 1", usize::MAX - 2, usize::MAX)
+        );
+    }
+
+    #[test]
+    fn cant_parse_negative_cells() {
+        let result = parse_with(
+            "SWAP -1, 2",
+            &ParseOptions {
+                signed: true,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            result.map_err(|e| e.error),
+            Err(ParseError::ArgParse(_))
+        ));
+    }
+
+    #[test]
+    fn reports_location_of_errors() {
+        let assembly = "LOAD 0\nXOR 1, 2\nSWAP 1\n  INC 99999999999999999999999\nloop: FOO 1";
+        let error = parse(assembly).unwrap_err();
+        assert_eq!(
+            error,
+            Diagnostic {
+                line: 3,
+                column: 1,
+                length: 6,
+                token: String::from("SWAP 1"),
+                error: ParseError::Arity {
+                    operation: String::from("SWAP"),
+                    expected: 2,
+                    found: 1
+                }
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "SWAP expects 2 operands, got 1 at line 3:1"
+        );
+
+        let errors = parse_all(assembly).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!((errors[1].line, errors[1].column), (4, 7));
+        assert_eq!(errors[1].token, "99999999999999999999999");
+        assert_eq!(
+            errors[1].to_string(),
+            "Invalid operand 99999999999999999999999 at line 4:7 (number too large to fit in target type)"
+        );
+        assert_eq!(
+            (errors[2].line, errors[2].column, errors[2].length),
+            (5, 7, 3)
+        );
+        assert_eq!(errors[2].to_string(), "Invalid operation FOO at line 5:7");
+    }
+
+    #[test]
+    fn reports_unknown_label_location() {
+        let error = parse("INC 0\nJNZ 0, nowhere").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 8));
+        assert_eq!(error.to_string(), "Unknown label nowhere at line 2:8");
+    }
+
+    #[test]
+    fn rejects_trailing_tokens() {
+        let unexpected = |token: &str| Err(ParseError::UnexpectedToken(String::from(token)));
+        assert_eq!("LOAD 3ß".parse::<Instruction>(), unexpected("ß"));
+        assert_eq!("LOAD 3 4".parse::<Instruction>(), unexpected("4"));
+        assert_eq!("LOAD 3,".parse::<Instruction>(), unexpected(","));
+        assert_eq!("SWAP 1,, 2".parse::<Instruction>(), unexpected(","));
+        assert_eq!("LOAD; 3".parse::<Instruction>(), unexpected(";"));
+        assert_eq!(
+            "  SWAP 1 ,2  ".parse::<Instruction>(),
+            Ok(Instruction::Swap(1, 2))
+        );
+
+        let error = parse("LOAD 3\nLOAD 3ß").unwrap_err();
+        assert_eq!((error.line, error.column, error.length), (2, 7, 1));
+        assert_eq!(error.to_string(), "Unexpected token ß at line 2:7");
+    }
+
+    #[test]
+    fn checks_operand_count() {
+        let arity = |found| {
+            Err(ParseError::Arity {
+                operation: String::from("SWAP"),
+                expected: 2,
+                found,
+            })
+        };
+        assert_eq!("SWAP 1".parse::<Instruction>(), arity(1));
+        assert_eq!("SWAP 1, 2, 3".parse::<Instruction>(), arity(3));
+        assert_eq!("SWAP".parse::<Instruction>(), Err(ParseError::NoArgs));
+    }
+
+    #[test]
+    fn lenient_mode_keeps_original_grammar() {
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let parsed = parse_with("LOAD 3ß\nSWAP 1, 2, 3\nXOR 1, 2 junk", &lenient).unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                Instruction::Load(3),
+                Instruction::Swap(1, 2),
+                Instruction::Xor(1, 2)
+            ]
+        );
+        assert!(matches!(
+            parse_instruction("SWAP 1", &lenient),
+            Err(ParseError::Arity { found: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_comments_and_directives() {
+        let source = "; copies a constant around\n\
+                      .cells 3\n\
+                      .equ START, 4   # initial value\n\
+                      .target START, 0, START\n\
+                      \n\
+                      LOAD START ; first cell\n\
+                      SWAP 0, 2\n\
+                      LOAD START\n";
+        let assembly = parse_assembly(source).unwrap();
+        assert_eq!(assembly.cells, Some(3));
+        assert_eq!(assembly.target, Some(vec![4, 0, 4]));
+        assert_eq!(
+            assembly.program,
+            vec![
+                Instruction::Load(4),
+                Instruction::Swap(0, 2),
+                Instruction::Load(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_directive_errors() {
+        let errors = parse_assembly(".cells\n.speed 3\nx: INC 0\n.equ x, 1").unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                ".cells expects 1 operands, got 0 at line 1:2",
+                "Unknown directive .speed at line 2:2",
+                "x is already defined at line 4:6",
+            ]
+        );
+    }
+
+    #[test]
+    fn signed_targets() {
+        let signed = ParseOptions {
+            signed: true,
+            ..Default::default()
+        };
+        let assembly = parse_assembly_with(".target -1, 2", &signed).unwrap();
+        assert_eq!(assembly.target, Some(crate::cpu::from_signed(&[-1, 2])));
+        assert!(parse_assembly(".target -1").is_err());
+    }
+
+    #[test]
+    fn parses_hex_binary_and_character_literals() {
+        let program =
+            parse("load 0x1F\nLoadTo 1, 0b1010\nLOAD 'A'\nLOAD '\\n'\nLOAD ';' ; comment").unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Instruction::Load(31),
+                Instruction::LoadTo(1, 10),
+                Instruction::Load(65),
+                Instruction::Load(10),
+                Instruction::Load(59)
+            ]
+        );
+        assert_eq!(
+            parse_program::<Instruction>("XOR 0, 1\nLOAD 'x'").unwrap()[1],
+            Instruction::Load(120)
+        );
+
+        let signed = ParseOptions {
+            signed: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_instruction("LOAD -0x10", &signed),
+            Ok(Instruction::Load(-16isize as usize))
+        );
+        assert!(matches!(
+            parse_instruction("LOAD -0x10", &ParseOptions::default()),
+            Err(ParseError::ArgParse(_))
+        ));
+        assert!(matches!(
+            parse_instruction("LOAD 0b102", &ParseOptions::default()),
+            Err(ParseError::UnexpectedToken(_))
+        ));
+    }
+
+    #[test]
+    fn output_styles_round_trip() {
+        let program = vec![
+            Instruction::Load(0x1F),
+            Instruction::Xor(0, 1),
+            Instruction::LoadTo(1, -2isize as usize),
+        ];
+        let hex = OutputOptions {
+            signed: true,
+            hex: true,
+            ..Default::default()
+        };
+        let lowercase = OutputOptions {
+            hex: true,
+            lowercase: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            output_with(&program, &hex),
+            "LOAD 0x1F\nXOR 0, 1\nLOADTO 1, -0x2"
+        );
+        assert_eq!(
+            output_with(&program, &lowercase),
+            "load 0x1f\nxor 0, 1\nloadto 1, 0xfffffffffffffffe"
+        );
+
+        let signed = ParseOptions {
+            signed: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_with(&output_with(&program, &hex), &signed).unwrap(),
+            program
+        );
+        assert_eq!(parse(&output_with(&program, &lowercase)).unwrap(), program);
+    }
+
+    proptest! {
+        #[test]
+        fn output_parses_back(program in strategies::program(8, 12)) {
+            prop_assert_eq!(parse(&output(&program)).unwrap(), program);
+        }
+
+        #[test]
+        fn styled_output_parses_back(
+            program in strategies::program(8, 12),
+            signed: bool,
+            hex: bool,
+            lowercase: bool,
+        ) {
+            let text = output_with(&program, &OutputOptions { signed, hex, lowercase });
+            let options = ParseOptions { signed, ..Default::default() };
+            prop_assert_eq!(parse_with(&text, &options).unwrap(), program);
+        }
+    }
 }
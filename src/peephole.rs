@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use crate::analysis;
+use crate::config::{SearchConfig, SearchLimits};
+use crate::cpu::{validate, ExecError, Instruction};
+use crate::iters::product_iter;
+use crate::symbolic::{self, Expr};
+use crate::verify;
+
+pub const DEFAULT_WINDOW: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeepholeOptions {
+    // instructions per window
+    pub window: usize,
+    // replace parts of the search space derived from each window; replacements are always
+    // shorter than the window
+    pub limits: SearchLimits,
+    // immediates are searched by signed magnitude
+    pub signed: bool,
+}
+
+impl Default for PeepholeOptions {
+    fn default() -> Self {
+        PeepholeOptions {
+            window: DEFAULT_WINDOW,
+            limits: SearchLimits::default(),
+            signed: false,
+        }
+    }
+}
+
+// a window can go when it doesn't jump and nothing jumps into its middle
+fn replaceable(program: &[Instruction], start: usize, end: usize) -> bool {
+    program[start..end]
+        .iter()
+        .all(|instruction| instruction.jump_target().is_none())
+        && program
+            .iter()
+            .filter_map(Instruction::jump_target)
+            .all(|target| target <= start || target >= end)
+}
+
+// points jumps past a window that is getting `removed` instructions shorter
+fn relocate(instruction: Instruction, end: usize, removed: usize) -> Instruction {
+    let shift = |target: usize| match target >= end {
+        true => target - removed,
+        false => target,
+    };
+    match instruction {
+        Instruction::Jmp(target) => Instruction::Jmp(shift(target)),
+        Instruction::Jz(memory, target) => Instruction::Jz(memory, shift(target)),
+        Instruction::Jnz(memory, target) => Instruction::Jnz(memory, shift(target)),
+        instruction => instruction,
+    }
+}
+
+// the shortest program, shorter than `window`, that leaves every `live` cell as `window` does
+// whatever the cells hold before; candidates are tried on a few inputs first and only accepted
+// once symbolic execution proves them
+fn shorten(
+    window: &[Instruction],
+    live: &[bool],
+    cells: usize,
+    options: &PeepholeOptions,
+) -> Option<Vec<Instruction>> {
+    let config = SearchConfig::covering(window, cells, options.signed).limited(&options.limits);
+    let live: Vec<usize> = (0..cells).filter(|&cell| live[cell]).collect();
+    let same_on_live =
+        |left: &[usize], right: &[usize]| live.iter().all(|&cell| left[cell] == right[cell]);
+    let proven = |left: &[Expr], right: &[Expr]| live.iter().all(|&cell| left[cell] == right[cell]);
+
+    let formulas = symbolic::execute(window, cells, config.max_steps).ok()?;
+    let probes = symbolic::probes(cells);
+    let expected = probes
+        .iter()
+        .map(|input| verify::run(window, input, config.max_steps))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+
+    let equivalent = |candidate: &Vec<Instruction>| {
+        probes.iter().zip(&expected).all(|(input, expected)| {
+            verify::run(candidate, input, config.max_steps)
+                .is_ok_and(|state| same_on_live(&state, expected))
+        }) && symbolic::execute(candidate, cells, config.max_steps)
+            .is_ok_and(|candidate| proven(&candidate, &formulas))
+    };
+
+    (0..window.len()).find_map(|length| {
+        let possible_instructions = config.instructions(length);
+        product_iter(&possible_instructions, length).find(equivalent)
+    })
+}
+
+// the instructions of a window and the cells live after it
+type Window = (Vec<Instruction>, Vec<bool>);
+
+// slides a window over `program`, replacing windows with shorter ones that leave the live cells
+// the same, until no window gets shorter; the result leaves every initial state as `program` does
+pub fn optimize(
+    program: &[Instruction],
+    cells: usize,
+    options: &PeepholeOptions,
+) -> Result<Vec<Instruction>, ExecError> {
+    validate(program, cells)?;
+    // every cell counts at the end
+    let outputs: Vec<usize> = (0..cells).collect();
+    let mut program = program.to_vec();
+    // windows come back after every change, so searches are remembered
+    let mut shortened: HashMap<Window, Option<Vec<Instruction>>> = HashMap::new();
+
+    loop {
+        let mut changed = false;
+        let mut live = analysis::live_cells(&program, cells, &outputs);
+        let mut start = 0;
+
+        while start < program.len() && options.window > 0 {
+            let end = (start + options.window).min(program.len());
+            if !replaceable(&program, start, end) {
+                start += 1;
+                continue;
+            }
+
+            let key = (program[start..end].to_vec(), live[end].clone());
+            let shorter = shortened
+                .entry(key)
+                .or_insert_with_key(|(window, live)| shorten(window, live, cells, options))
+                .clone();
+            match shorter {
+                Some(shorter) => {
+                    let removed = end - start - shorter.len();
+                    program = program
+                        .iter()
+                        .map(|instruction| relocate(*instruction, end, removed))
+                        .collect();
+                    program.splice(start..end, shorter);
+                    live = analysis::live_cells(&program, cells, &outputs);
+                    changed = true;
+                    // the instructions before may now combine with the replacement
+                    start = start.saturating_sub(options.window - 1);
+                }
+                None => start += 1,
+            }
+        }
+
+        if !changed {
+            return Ok(program);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn optimized(source: &str, cells: usize) -> Vec<Instruction> {
+        let program = parse(source).unwrap();
+        let optimized = optimize(&program, cells, &PeepholeOptions::default()).unwrap();
+        assert_eq!(
+            verify::equivalent(&program, &optimized, cells, &[0, 1, 2, 7]),
+            Ok(())
+        );
+        optimized
+    }
+
+    #[test]
+    fn replaces_windows_with_shorter_ones() {
+        let source = "LOAD 2\nSWAP 0, 1\nSWAP 0, 1\nINC 0\nDEC 0\nXOR 1, 1\nXOR 1, 1";
+        assert_eq!(optimized(source, 2), parse("XOR 1, 1\nLOAD 2").unwrap());
+        assert_eq!(optimized("INC 0\nDEC 1", 2), parse("INC 0\nDEC 1").unwrap());
+    }
+
+    #[test]
+    fn drops_values_nothing_reads() {
+        assert_eq!(optimized("LOAD 1\nLOAD 2", 1), parse("LOAD 2").unwrap());
+        // cell 1 is overwritten before anything reads it
+        assert_eq!(
+            optimized("INC 1\nINC 1\nADD 0, 0\nMOV 1, 0", 2),
+            parse("ADD 0, 0\nMOV 1, 0").unwrap()
+        );
+    }
+
+    #[test]
+    fn keeps_jumps_pointing_at_the_same_instructions() {
+        let source = "INC 2\nDEC 2\nLOAD 3\nINC 1\nINC 1\nDEC 1\nDEC 0\nJNZ 0, 3";
+        assert_eq!(
+            optimized(source, 3),
+            parse("LOAD 3\nINC 1\nDEC 0\nJNZ 0, 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn preserves_long_programs() {
+        let operations = [
+            "LOAD 3",
+            "SWAP 0, 2",
+            "XOR 1, 0",
+            "INC 2",
+            "DEC 1",
+            "ADD 0, 1",
+            "XOR 2, 2",
+            "SWAP 1, 2",
+        ];
+        // a fixed pseudo-random sequence
+        let mut seed = 7usize;
+        let source = (0..24)
+            .map(|_| {
+                seed = (seed * 1103515245 + 12345) % (1 << 31);
+                operations[(seed >> 16) % operations.len()]
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let program = parse(&source).unwrap();
+        assert!(optimized(&source, 3).len() < program.len());
+    }
+}
@@ -0,0 +1,444 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+use tokio::task;
+
+#[cfg(feature = "cache")]
+use crate::cache::{Cache, CacheKey};
+use crate::config::SearchConfig;
+use crate::cpu::Instruction;
+use crate::superoptimizer_sat::SatError;
+use crate::{
+    superoptimizer, superoptimizer_async, superoptimizer_rayon, superoptimizer_sat,
+    superoptimizer_threads,
+};
+
+// bumped whenever a field of the report changes meaning or is removed
+pub const REPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Backend {
+    Simple,
+    Rayon,
+    Threads,
+    Async,
+    Sat,
+}
+
+impl Backend {
+    pub fn all() -> [Backend; 5] {
+        [
+            Backend::Simple,
+            Backend::Rayon,
+            Backend::Threads,
+            Backend::Async,
+            Backend::Sat,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Simple => "simple",
+            Backend::Rayon => "rayon",
+            Backend::Threads => "threads",
+            Backend::Async => "async",
+            Backend::Sat => "sat",
+        }
+    }
+
+    // consults the cache `SUPEROPTIMUSPRIME_CACHE` points at, if any; only the SAT backend
+    // fails, on searches it can't encode
+    pub fn superoptimize(
+        &self,
+        config: &SearchConfig,
+        target_state: &[usize],
+    ) -> Result<Option<Vec<Instruction>>, SatError> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = Cache::from_env() {
+            return self
+                .superoptimize_with_cache(&cache, config, target_state)
+                .map(|(program, _)| program);
+        }
+        self.search(config, target_state)
+    }
+
+    fn search(
+        &self,
+        config: &SearchConfig,
+        target_state: &[usize],
+    ) -> Result<Option<Vec<Instruction>>, SatError> {
+        let program = match self {
+            Backend::Simple => superoptimizer::superoptimize_with_config(config, target_state),
+            Backend::Rayon => superoptimizer_rayon::superoptimize_with_config(config, target_state),
+            Backend::Threads => {
+                superoptimizer_threads::superoptimize_with_config(config, target_state)
+            }
+            Backend::Async => block_on_async(config, target_state),
+            // the same answer as the others as long as intermediate values stay small
+            Backend::Sat => superoptimizer_sat::superoptimize_with_config(config, target_state)?,
+        };
+        Ok(program)
+    }
+
+    // the SAT backend only searches programs whose values fit this many bits, so its misses
+    // prove nothing beyond them; `None` for the others, which try every value
+    pub fn width(&self, config: &SearchConfig, target_state: &[usize]) -> Option<u32> {
+        match self {
+            Backend::Sat => superoptimizer_sat::width_for(config, target_state).ok(),
+            _ => None,
+        }
+    }
+}
+
+// runs the async search on the caller's runtime when there is one, since starting a second
+// runtime inside it panics, and on a runtime of its own otherwise
+fn block_on_async(config: &SearchConfig, target_state: &[usize]) -> Option<Vec<Instruction>> {
+    let search = || superoptimizer_async::superoptimize_with_config(config, target_state);
+    let own_runtime = || {
+        Runtime::new()
+            .expect("failed to start the async runtime")
+            .block_on(search())
+    };
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            task::block_in_place(|| handle.block_on(search()))
+        }
+        // a current-thread runtime can't be blocked in place, so the search runs beside it
+        Ok(_) => std::thread::scope(|scope| scope.spawn(own_runtime).join())
+            .expect("the async search panicked"),
+        Err(_) => own_runtime(),
+    }
+}
+
+#[cfg(feature = "cache")]
+impl Backend {
+    // the answer `cache` holds, or else the backend's, which is then recorded; on a hit, also
+    // the name of the backend whose search the answer came from
+    pub fn superoptimize_with_cache(
+        &self,
+        cache: &Cache,
+        config: &SearchConfig,
+        target_state: &[usize],
+    ) -> Result<(Option<Vec<Instruction>>, Option<String>), SatError> {
+        let entry = cache.get(&CacheKey::new(config, target_state));
+        if let Some(entry) = entry {
+            if let Some(program) = entry.answer_within(config.max_instructions_length) {
+                return Ok((program, Some(entry.statistics.backend)));
+            }
+        }
+        let start = Instant::now();
+        let program = self.search(config, target_state)?;
+        // the others all return the shortest program, which answers every length limit; the SAT
+        // backend only covers small values, so neither its programs nor its misses settle the
+        // search for the others
+        if *self != Backend::Sat {
+            // a cache that can't be written only costs the next run its time
+            let _ = cache.record(config, target_state, &program, self.name(), start.elapsed());
+        }
+        Ok((program, None))
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::all()
+            .into_iter()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| format!("Unknown backend {}", s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchStatistics {
+    // wall-clock time of the search alone, in microseconds
+    pub elapsed_micros: u64,
+    // the program came from the cache rather than a search
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cached: bool,
+    // for a cached program, the backend whose search recorded it
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cached_from: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchResult {
+    // `None` when no program within the limits reaches the target
+    pub program: Option<Vec<Instruction>>,
+    // the bits values were limited to, when the search didn't try all of them
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub width: Option<u32>,
+    pub statistics: SearchStatistics,
+}
+
+// everything needed to reproduce and compare a search; as JSON:
+//   { "version": 1, "backend": "simple" | "rayon" | "threads" | "async" | "sat", "target": [0, 2],
+//     "config": { "max_instructions_length": 2, "max_memory_cells": 2, "max_value": 3,
+//                 "max_steps": 1000, "instruction_set": { "operations": ["LOAD"], "signed": false } },
+//     "result": { "program": [{ "LOAD": 2 }, { "SWAP": [1, 0] }] | null, "width": 3 | null,
+//                 "statistics": { "elapsed_micros": 120, "cached": false,
+//                                 "cached_from": "rayon" | null } } }
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchReport {
+    pub version: u32,
+    pub backend: Backend,
+    pub target: Vec<usize>,
+    pub config: SearchConfig,
+    pub result: SearchResult,
+}
+
+impl SearchReport {
+    // runs `backend` and times it, through the cache `SUPEROPTIMUSPRIME_CACHE` points at, if any
+    pub fn run(
+        backend: Backend,
+        config: &SearchConfig,
+        target_state: &[usize],
+    ) -> Result<SearchReport, SatError> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = Cache::from_env() {
+            return SearchReport::run_with_cache(backend, &cache, config, target_state);
+        }
+        SearchReport::timed(backend, config, target_state, || {
+            Ok((backend.search(config, target_state)?, None))
+        })
+    }
+
+    fn timed(
+        backend: Backend,
+        config: &SearchConfig,
+        target_state: &[usize],
+        search: impl FnOnce() -> Result<(Option<Vec<Instruction>>, Option<String>), SatError>,
+    ) -> Result<SearchReport, SatError> {
+        let start = Instant::now();
+        let (program, cached_from) = search()?;
+        let elapsed_micros = start.elapsed().as_micros() as u64;
+        // cached answers come from searches over every value
+        let width = match cached_from {
+            Some(_) => None,
+            None => backend.width(config, target_state),
+        };
+
+        Ok(SearchReport {
+            version: REPORT_VERSION,
+            backend,
+            target: target_state.to_vec(),
+            config: config.clone(),
+            result: SearchResult {
+                program,
+                width,
+                statistics: SearchStatistics {
+                    elapsed_micros,
+                    cached: cached_from.is_some(),
+                    cached_from,
+                },
+            },
+        })
+    }
+}
+
+#[cfg(feature = "cache")]
+impl SearchReport {
+    pub fn run_with_cache(
+        backend: Backend,
+        cache: &Cache,
+        config: &SearchConfig,
+        target_state: &[usize],
+    ) -> Result<SearchReport, SatError> {
+        SearchReport::timed(backend, config, target_state, || {
+            backend.superoptimize_with_cache(cache, config, target_state)
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SearchReport {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    // rejects reports written with a different `REPORT_VERSION`
+    pub fn from_json(json: &str) -> serde_json::Result<SearchReport> {
+        let report: SearchReport = serde_json::from_str(json)?;
+        if report.version != REPORT_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported report version {}",
+                report.version
+            )));
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{InstructionSet, CPU};
+    use proptest::prelude::*;
+
+    #[test]
+    fn backends_parse_by_name() {
+        for backend in Backend::all() {
+            assert_eq!(backend.to_string().parse::<Backend>(), Ok(backend));
+        }
+        assert!("gpu".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn backends_agree() {
+        let config = SearchConfig::new(2, 2, 3);
+        for backend in Backend::all() {
+            let report = SearchReport::run(backend, &config, &[2, 0]).unwrap();
+            assert_eq!(report.version, REPORT_VERSION);
+            assert_eq!(report.result.program, Some(vec![Instruction::Load(2)]));
+            // the searches over longer programs find one too, but the shortest wins, and
+            // enumerating backends pick the first of that length like the sequential search
+            let program = SearchReport::run(backend, &config, &[0, 0])
+                .unwrap()
+                .result
+                .program;
+            match backend {
+                Backend::Sat => assert_eq!(program.map(|program| program.len()), Some(1)),
+                _ => assert_eq!(program, Some(vec![Instruction::Load(0)])),
+            }
+            // out of reach with values below 3, which must not hang any backend
+            let report = SearchReport::run(backend, &config, &[5, 0]).unwrap();
+            assert_eq!(report.result.program, None);
+            // only the SAT backend's miss is limited to values of a few bits
+            let width = (backend == Backend::Sat).then_some(4);
+            assert_eq!(report.result.width, width);
+        }
+    }
+
+    #[test]
+    fn async_backend_runs_inside_a_runtime() {
+        let config = SearchConfig::new(2, 2, 3);
+        let expected = Some(vec![Instruction::Load(2)]);
+        for runtime in [
+            tokio::runtime::Builder::new_current_thread().build(),
+            tokio::runtime::Builder::new_multi_thread().build(),
+        ] {
+            let program = runtime
+                .unwrap()
+                .block_on(async { Backend::Async.search(&config, &[2, 0]).unwrap() });
+            assert_eq!(program, expected);
+        }
+    }
+
+    #[test]
+    fn reports_searches_the_sat_backend_cannot_encode() {
+        let config = SearchConfig::new(2, 2, 3).with_instruction_set(InstructionSet::new(&["ADD"]));
+        assert_eq!(
+            SearchReport::run(Backend::Sat, &config, &[2, 0]),
+            Err(SatError::UnsupportedOperation("ADD".to_string()))
+        );
+        assert!(SearchReport::run(Backend::Simple, &config, &[2, 0]).is_ok());
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn backends_share_the_cache() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Cache::open(directory.path()).unwrap();
+        let config = SearchConfig::new(2, 2, 3);
+
+        let first =
+            SearchReport::run_with_cache(Backend::Simple, &cache, &config, &[0, 2]).unwrap();
+        assert!(!first.result.statistics.cached);
+        for backend in Backend::all() {
+            let report = SearchReport::run_with_cache(backend, &cache, &config, &[0, 2]).unwrap();
+            assert!(report.result.statistics.cached);
+            assert_eq!(
+                report.result.statistics.cached_from.as_deref(),
+                Some("simple")
+            );
+            assert_eq!(report.result.width, None);
+            assert_eq!(report.result.program, first.result.program);
+        }
+
+        let miss = SearchReport::run_with_cache(Backend::Rayon, &cache, &config, &[5, 0]).unwrap();
+        assert_eq!(miss.result.program, None);
+        let proven =
+            SearchReport::run_with_cache(Backend::Threads, &cache, &config, &[5, 0]).unwrap();
+        assert!(proven.result.statistics.cached);
+        assert_eq!(proven.result.program, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn report_json_schema() {
+        let report =
+            SearchReport::run(Backend::Simple, &SearchConfig::new(2, 2, 3), &[0, 2]).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["version"], REPORT_VERSION);
+        assert_eq!(json["backend"], "simple");
+        assert_eq!(json["target"], serde_json::json!([0, 2]));
+        assert_eq!(
+            json["config"]["instruction_set"]["operations"],
+            serde_json::json!(["LOAD", "SWAP", "XOR", "INC"])
+        );
+        assert_eq!(
+            json["result"]["program"],
+            serde_json::json!([{ "LOAD": 2 }, { "SWAP": [1, 0] }])
+        );
+        assert!(json["result"]["statistics"]["elapsed_micros"].is_u64());
+        assert_eq!(json["result"]["statistics"]["cached"], false);
+        assert_eq!(
+            json["result"]["statistics"]["cached_from"],
+            serde_json::Value::Null
+        );
+        assert_eq!(json["result"]["width"], serde_json::Value::Null);
+
+        assert_eq!(
+            SearchReport::from_json(&report.to_json().unwrap()).unwrap(),
+            report
+        );
+        let future = report
+            .to_json()
+            .unwrap()
+            .replacen("\"version\": 1", "\"version\": 2", 1);
+        assert!(SearchReport::from_json(&future).is_err());
+    }
+
+    // targets some program of up to two instructions reaches, so every backend has one to find
+    fn reachable() -> impl Strategy<Value = Vec<usize>> {
+        let instructions = SearchConfig::new(2, 2, 4).instructions(2);
+        proptest::collection::vec(proptest::sample::select(instructions), 0..=2).prop_map(
+            |program| {
+                let mut cpu = CPU::new(2);
+                cpu.execute(&program);
+                cpu.state
+            },
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn backends_reach_the_target(target in reachable()) {
+            let config = SearchConfig::new(2, 2, 4);
+            for backend in Backend::all() {
+                // searched directly, since a cache `SUPEROPTIMUSPRIME_CACHE` points at would answer instead
+                let program = backend.search(&config, &target).unwrap();
+                prop_assert!(program.is_some(), "{} found nothing", backend);
+                let mut cpu = CPU::new(2);
+                cpu.execute(&program.unwrap());
+                prop_assert_eq!(&cpu.state, &target, "{}", backend);
+            }
+        }
+    }
+}
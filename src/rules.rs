@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::config::{SearchConfig, DEFAULT_MAX_STEPS};
+use crate::cpu::Instruction;
+use crate::isa::InstructionSemantics;
+use crate::parser::ParseError;
+use crate::symbolic::{self, Equivalence};
+use crate::verify;
+
+// bumped whenever rule files change format or older rules stop being valid
+pub const RULES_VERSION: u32 = 1;
+
+fn header() -> String {
+    format!("# superoptimusprime rules v{}", RULES_VERSION)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RuleError {
+    // the first line isn't the header of this version; holds what it was instead
+    Version(String),
+    Instruction { line: usize, error: ParseError },
+    // not `pattern => replacement` with a replacement shorter than the pattern
+    Malformed { line: usize },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::Version(found) => {
+                write!(f, "Expected \"{}\", found \"{}\"", header(), found)
+            }
+            RuleError::Instruction { line, error } => write!(f, "{} at line {}", error, line),
+            RuleError::Malformed { line } => write!(
+                f,
+                "Expected pattern => shorter replacement at line {}",
+                line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+// `pattern` leaves every state exactly as `replacement` does
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub pattern: Vec<Instruction>,
+    pub replacement: Vec<Instruction>,
+}
+
+fn join(program: &[Instruction]) -> String {
+    let instructions = program.iter().map(ToString::to_string);
+    instructions.collect::<Vec<String>>().join("; ")
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let replacement = join(&self.replacement);
+        match replacement.is_empty() {
+            true => write!(f, "{} =>", join(&self.pattern)),
+            false => write!(f, "{} => {}", join(&self.pattern), replacement),
+        }
+    }
+}
+
+fn cells_used(program: &[Instruction]) -> HashSet<usize> {
+    let cells = program.iter().flat_map(|instruction| {
+        let mut cells = instruction.reads();
+        cells.extend(instruction.writes());
+        cells
+    });
+    cells.collect()
+}
+
+// the final states on the probe inputs; equivalent programs always share it
+fn fingerprint(program: &[Instruction], cells: usize) -> Vec<Vec<usize>> {
+    symbolic::probes(cells)
+        .iter()
+        .map(|input| verify::run(program, input, DEFAULT_MAX_STEPS).unwrap_or_default())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleDatabase {
+    rules: Vec<Rule>,
+    // the rule for each pattern
+    index: HashMap<Vec<Instruction>, usize>,
+    longest: usize,
+}
+
+impl RuleDatabase {
+    pub fn new(rules: Vec<Rule>) -> RuleDatabase {
+        let mut database = RuleDatabase::default();
+        for rule in rules {
+            database.longest = database.longest.max(rule.pattern.len());
+            database
+                .index
+                .insert(rule.pattern.clone(), database.rules.len());
+            database.rules.push(rule);
+        }
+        database
+    }
+
+    // enumerates every sequence of up to `config.max_instructions_length` instructions, grouped
+    // by fingerprint; each one symbolic execution proves equivalent to a shorter sequence
+    // becomes a rule. Sequences holding a shorter rule's pattern are left out, as they can't be
+    // optimal, and so is every jump
+    pub fn generate(config: &SearchConfig) -> RuleDatabase {
+        let cells = config.max_memory_cells;
+        let alphabet: Vec<Instruction> = config
+            .instructions(1)
+            .into_iter()
+            .filter(|instruction| instruction.jump_target().is_none())
+            .collect();
+
+        // the optimal programs found so far, by fingerprint
+        let mut classes: HashMap<Vec<Vec<usize>>, Vec<Vec<Instruction>>> = HashMap::new();
+        classes.insert(fingerprint(&[], cells), vec![vec![]]);
+        let mut optimal: Vec<Vec<Instruction>> = vec![vec![]];
+        let mut optimal_so_far: HashSet<Vec<Instruction>> = HashSet::new();
+        let mut rules = Vec::new();
+
+        for length in 1..=config.max_instructions_length {
+            let mut next = Vec::new();
+            for prefix in &optimal {
+                for instruction in &alphabet {
+                    let mut candidate = prefix.clone();
+                    candidate.push(*instruction);
+                    if length > 1 && !optimal_so_far.contains(&candidate[1..]) {
+                        continue;
+                    }
+
+                    let representatives =
+                        classes.entry(fingerprint(&candidate, cells)).or_default();
+                    let equivalent = representatives.iter().find(|representative| {
+                        symbolic::equivalent(representative, &candidate, cells, config.max_steps)
+                            == Ok(Equivalence::Proven)
+                    });
+                    match equivalent {
+                        Some(representative) if representative.len() < length => {
+                            // a replacement touching other cells wouldn't fit every program
+                            if cells_used(representative).is_subset(&cells_used(&candidate)) {
+                                rules.push(Rule {
+                                    pattern: candidate,
+                                    replacement: representative.clone(),
+                                });
+                            }
+                        }
+                        Some(_) => next.push(candidate),
+                        None => {
+                            representatives.push(candidate.clone());
+                            next.push(candidate);
+                        }
+                    }
+                }
+            }
+            optimal_so_far.extend(next.iter().cloned());
+            optimal = next;
+        }
+
+        RuleDatabase::new(rules)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn from_text(text: &str) -> Result<RuleDatabase, RuleError> {
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or("").trim();
+        if first != header() {
+            return Err(RuleError::Version(first.to_string()));
+        }
+
+        let mut rules = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line_number = index + 2;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, replacement) = line
+                .split_once("=>")
+                .ok_or(RuleError::Malformed { line: line_number })?;
+            let parse = |side: &str| {
+                side.split(';')
+                    .map(str::trim)
+                    .filter(|instruction| !instruction.is_empty())
+                    .map(|instruction| {
+                        instruction.parse().map_err(|error| RuleError::Instruction {
+                            line: line_number,
+                            error,
+                        })
+                    })
+                    .collect::<Result<Vec<Instruction>, RuleError>>()
+            };
+            let rule = Rule {
+                pattern: parse(pattern)?,
+                replacement: parse(replacement)?,
+            };
+            // rewriting has to make progress
+            if rule.replacement.len() >= rule.pattern.len() {
+                return Err(RuleError::Malformed { line: line_number });
+            }
+            rules.push(rule);
+        }
+        Ok(RuleDatabase::new(rules))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![header()];
+        lines.extend(self.rules.iter().map(ToString::to_string));
+        lines.join("\n") + "\n"
+    }
+
+    // rewrites every straight-line stretch of `program` left to right, always with the longest
+    // matching pattern; after a rewrite only the few instructions before it are looked at again,
+    // so the time is linear in the length of the program
+    pub fn apply(&self, program: &[Instruction]) -> Vec<Instruction> {
+        // jumps and the instructions they land on cut the program into blocks
+        let targets: HashSet<usize> = program
+            .iter()
+            .filter_map(Instruction::jump_target)
+            .collect();
+        let mut blocks: Vec<(usize, usize)> = Vec::new();
+        let mut start = 0;
+        for (index, instruction) in program.iter().enumerate() {
+            if targets.contains(&index) && start < index {
+                blocks.push((start, index));
+                start = index;
+            }
+            if instruction.jump_target().is_some() {
+                if start < index {
+                    blocks.push((start, index));
+                }
+                blocks.push((index, index + 1));
+                start = index + 1;
+            }
+        }
+        if start < program.len() {
+            blocks.push((start, program.len()));
+        }
+
+        // where each block starts once rewritten, to point the jumps at
+        let mut moved = HashMap::new();
+        let mut rewritten = Vec::with_capacity(program.len());
+        for (start, end) in blocks {
+            moved.insert(start, rewritten.len());
+            match program[start].jump_target() {
+                Some(_) => rewritten.push(program[start]),
+                None => rewritten.extend(self.rewrite(&program[start..end])),
+            }
+        }
+        moved.insert(program.len(), rewritten.len());
+
+        let relocate = |target: usize| moved.get(&target).copied().unwrap_or(target);
+        rewritten
+            .into_iter()
+            .map(|instruction| match instruction {
+                Instruction::Jmp(target) => Instruction::Jmp(relocate(target)),
+                Instruction::Jz(memory, target) => Instruction::Jz(memory, relocate(target)),
+                Instruction::Jnz(memory, target) => Instruction::Jnz(memory, relocate(target)),
+                instruction => instruction,
+            })
+            .collect()
+    }
+
+    fn rewrite(&self, block: &[Instruction]) -> Vec<Instruction> {
+        // the instructions still to look at, next one last
+        let mut input: Vec<Instruction> = block.iter().rev().copied().collect();
+        let mut output: Vec<Instruction> = Vec::with_capacity(block.len());
+
+        while let Some(instruction) = input.pop() {
+            output.push(instruction);
+            let matched = (1..=self.longest.min(output.len()))
+                .rev()
+                .find_map(|length| self.index.get(&output[output.len() - length..]));
+            if let Some(&rule) = matched {
+                let rule = &self.rules[rule];
+                output.truncate(output.len() - rule.pattern.len());
+                input.extend(rule.replacement.iter().rev());
+                // the replacement may complete a pattern with what came before
+                let back = self.longest.saturating_sub(1).min(output.len());
+                for _ in 0..back {
+                    input.push(output.pop().unwrap());
+                }
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn database() -> RuleDatabase {
+        RuleDatabase::generate(&SearchConfig::new(2, 2, 2))
+    }
+
+    #[test]
+    fn generates_proven_shortening_rules() {
+        let database = database();
+        let rule = |pattern: &str, replacement: &str| Rule {
+            pattern: parse(pattern).unwrap(),
+            replacement: parse(replacement).unwrap(),
+        };
+        assert!(database.rules().contains(&rule("SWAP 0, 1\nSWAP 0, 1", "")));
+        assert!(database.rules().contains(&rule("LOAD 1\nLOAD 0", "LOAD 0")));
+        assert!(database
+            .rules()
+            .contains(&rule("XOR 1, 1\nXOR 1, 1", "XOR 1, 1")));
+        for rule in database.rules() {
+            assert!(rule.replacement.len() < rule.pattern.len());
+            assert_eq!(
+                symbolic::equivalent(&rule.pattern, &rule.replacement, 2, DEFAULT_MAX_STEPS),
+                Ok(Equivalence::Proven)
+            );
+        }
+        // patterns never contain a shorter pattern
+        for rule in database.rules() {
+            for length in 1..rule.pattern.len() {
+                assert!(rule
+                    .pattern
+                    .windows(length)
+                    .all(|window| !database.index.contains_key(window)));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_rule_files() {
+        let database = database();
+        let text = database.to_text();
+        assert!(text.starts_with("# superoptimusprime rules v1\n"));
+        assert!(text.contains("\nSWAP 0, 1; SWAP 0, 1 =>\n"));
+        assert_eq!(
+            RuleDatabase::from_text(&text).unwrap().rules(),
+            database.rules()
+        );
+
+        let old = text.replacen("rules v1", "rules v0", 1);
+        assert!(matches!(
+            RuleDatabase::from_text(&old),
+            Err(RuleError::Version(_))
+        ));
+        let header = "# superoptimusprime rules v1\n";
+        assert_eq!(
+            RuleDatabase::from_text(&format!("{}INC 0 => INC 0; INC 0", header)),
+            Err(RuleError::Malformed { line: 2 })
+        );
+        assert!(matches!(
+            RuleDatabase::from_text(&format!("{}\nFOO 1 =>", header)),
+            Err(RuleError::Instruction { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn rewrites_programs() {
+        let database = database();
+        let program =
+            parse("LOAD 1\nSWAP 0, 1\nLOAD 1\nSWAP 0, 1\nSWAP 0, 1\nXOR 1, 1\nINC 1").unwrap();
+        let rewritten = database.apply(&program);
+        assert!(rewritten.len() < program.len());
+        assert_eq!(
+            verify::equivalent(&program, &rewritten, 2, &[0, 1, 5]),
+            Ok(())
+        );
+
+        // the loop body shrinks, and the jump follows it
+        let program = parse("LOAD 1\nLOAD 0\nINC 1\nSWAP 0, 1\nSWAP 0, 1\nJNZ 0, 2").unwrap();
+        let rewritten = database.apply(&program);
+        assert_eq!(rewritten, parse("LOAD 0\nINC 1\nJNZ 0, 1").unwrap());
+        assert_eq!(
+            verify::equivalent(&program, &rewritten, 2, &[0, 1, 5]),
+            Ok(())
+        );
+    }
+}
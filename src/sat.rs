@@ -0,0 +1,404 @@
+// a small CDCL SAT solver: two watched literals, first-UIP clause learning with
+// non-chronological backjumping and activity-based branching
+
+// a variable (numbered from 1) or its negation, as in DIMACS
+pub type Lit = i32;
+
+fn var(lit: Lit) -> usize {
+    lit.unsigned_abs() as usize
+}
+
+// slot of `lit` in the watch lists
+fn index(lit: Lit) -> usize {
+    2 * var(lit) + (lit < 0) as usize
+}
+
+#[derive(Debug, Default)]
+pub struct Solver {
+    variables: usize,
+    clauses: Vec<Vec<Lit>>,
+    units: Vec<Lit>,
+    // an empty clause was added
+    contradiction: bool,
+    watches: Vec<Vec<usize>>,
+    assignment: Vec<Option<bool>>,
+    level: Vec<usize>,
+    // the clause that implied each variable; for those, the implied literal comes first
+    reason: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    // where each decision level starts on the trail
+    levels: Vec<usize>,
+    propagated: usize,
+    activity: Vec<f64>,
+    bump: f64,
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver {
+            watches: vec![Vec::new(); 2],
+            assignment: vec![None],
+            level: vec![0],
+            reason: vec![None],
+            activity: vec![0.0],
+            bump: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_var(&mut self) -> Lit {
+        self.variables += 1;
+        self.watches.extend([Vec::new(), Vec::new()]);
+        self.assignment.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.activity.push(0.0);
+        self.variables as Lit
+    }
+
+    pub fn variables(&self) -> usize {
+        self.variables
+    }
+
+    pub fn add_clause(&mut self, literals: &[Lit]) {
+        let mut clause = literals.to_vec();
+        clause.sort();
+        clause.dedup();
+        // tautologies always hold
+        if clause.iter().any(|lit| clause.contains(&-lit)) {
+            return;
+        }
+        match clause.len() {
+            0 => self.contradiction = true,
+            1 => self.units.push(clause[0]),
+            _ => {
+                self.attach(clause);
+            }
+        }
+    }
+
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let id = self.clauses.len();
+        self.watches[index(clause[0])].push(id);
+        self.watches[index(clause[1])].push(id);
+        self.clauses.push(clause);
+        id
+    }
+
+    pub fn value(&self, lit: Lit) -> Option<bool> {
+        self.assignment[var(lit)].map(|value| value == (lit > 0))
+    }
+
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let variable = var(lit);
+        self.assignment[variable] = Some(lit > 0);
+        self.level[variable] = self.levels.len();
+        self.reason[variable] = reason;
+        self.trail.push(lit);
+    }
+
+    // assigns what the clauses imply; returns a clause that became false, if any
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let falsified = -self.trail[self.propagated];
+            self.propagated += 1;
+
+            let watching = std::mem::take(&mut self.watches[index(falsified)]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+
+            for (position, &id) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[position..]);
+                    break;
+                }
+                let clause = &mut self.clauses[id];
+                if clause[0] == falsified {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.assignment[var(first)].map(|value| value == (first > 0)) == Some(true) {
+                    kept.push(id);
+                    continue;
+                }
+
+                // move the watch to a literal that isn't false, if there is one
+                let replacement = (2..self.clauses[id].len()).find(|&k| {
+                    let lit = self.clauses[id][k];
+                    self.value(lit) != Some(false)
+                });
+                match replacement {
+                    Some(k) => {
+                        self.clauses[id].swap(1, k);
+                        let watched = self.clauses[id][1];
+                        self.watches[index(watched)].push(id);
+                    }
+                    None => {
+                        kept.push(id);
+                        match self.value(first) {
+                            Some(false) => conflict = Some(id),
+                            _ => self.assign(first, Some(id)),
+                        }
+                    }
+                }
+            }
+
+            self.watches[index(falsified)] = kept;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    fn bump_activity(&mut self, variable: usize) {
+        self.activity[variable] += self.bump;
+        if self.activity[variable] > 1e100 {
+            self.activity
+                .iter_mut()
+                .for_each(|activity| *activity *= 1e-100);
+            self.bump *= 1e-100;
+        }
+    }
+
+    // the first-UIP clause learnt from `conflict` and the level to jump back to
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let current = self.levels.len();
+        let mut seen = vec![false; self.variables + 1];
+        let mut learnt = vec![0];
+        let mut pending = 0;
+        let mut clause = conflict;
+        let mut implied: Option<Lit> = None;
+        let mut position = self.trail.len();
+
+        loop {
+            // a reason clause starts with the literal it implied, which is being resolved away
+            let skip = implied.is_some() as usize;
+            for k in skip..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let variable = var(lit);
+                if seen[variable] || self.level[variable] == 0 {
+                    continue;
+                }
+                seen[variable] = true;
+                self.bump_activity(variable);
+                match self.level[variable] == current {
+                    true => pending += 1,
+                    false => learnt.push(lit),
+                }
+            }
+
+            loop {
+                position -= 1;
+                if seen[var(self.trail[position])] {
+                    break;
+                }
+            }
+            let lit = self.trail[position];
+            seen[var(lit)] = false;
+            pending -= 1;
+            implied = Some(lit);
+            if pending == 0 {
+                break;
+            }
+            clause = self.reason[var(lit)].expect("implied literals have a reason");
+        }
+        learnt[0] = -implied.unwrap();
+
+        // the second watch goes on the literal assigned last, which sets the backjump level
+        let mut backjump = 0;
+        if learnt.len() > 1 {
+            let deepest = (1..learnt.len())
+                .max_by_key(|&k| self.level[var(learnt[k])])
+                .unwrap();
+            learnt.swap(1, deepest);
+            backjump = self.level[var(learnt[1])];
+        }
+        self.bump /= 0.95;
+        (learnt, backjump)
+    }
+
+    fn backtrack(&mut self, level: usize) {
+        if self.levels.len() <= level {
+            return;
+        }
+        let start = self.levels[level];
+        for lit in self.trail.drain(start..) {
+            self.assignment[var(lit)] = None;
+            self.reason[var(lit)] = None;
+        }
+        self.levels.truncate(level);
+        self.propagated = start;
+    }
+
+    fn decide(&mut self) -> Option<Lit> {
+        (1..=self.variables)
+            .filter(|&variable| self.assignment[variable].is_none())
+            .max_by(|&a, &b| {
+                self.activity[a]
+                    .total_cmp(&self.activity[b])
+                    .then(b.cmp(&a))
+            })
+            .map(|variable| -(variable as Lit))
+    }
+
+    // a satisfying assignment, indexed by variable (index 0 is unused), or `None` if there's none
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        if self.contradiction {
+            return None;
+        }
+        self.backtrack(0);
+        for lit in self.units.clone() {
+            match self.value(lit) {
+                Some(false) => return None,
+                Some(true) => {}
+                None => self.assign(lit, None),
+            }
+        }
+
+        loop {
+            match self.propagate() {
+                Some(_) if self.levels.is_empty() => return None,
+                Some(conflict) => {
+                    let (learnt, backjump) = self.analyze(conflict);
+                    self.backtrack(backjump);
+                    match learnt.len() {
+                        1 => {
+                            self.units.push(learnt[0]);
+                            self.assign(learnt[0], None);
+                        }
+                        _ => {
+                            let asserting = learnt[0];
+                            let id = self.attach(learnt);
+                            self.assign(asserting, Some(id));
+                        }
+                    }
+                }
+                None => match self.decide() {
+                    Some(lit) => {
+                        self.levels.push(self.trail.len());
+                        self.assign(lit, None);
+                    }
+                    None => {
+                        let model = self
+                            .assignment
+                            .iter()
+                            .map(|value| value.unwrap_or(false))
+                            .collect();
+                        return Some(model);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn satisfies(clauses: &[Vec<Lit>], model: &[bool]) -> bool {
+        clauses
+            .iter()
+            .all(|clause| clause.iter().any(|&lit| model[var(lit)] == (lit > 0)))
+    }
+
+    #[test]
+    fn solves_small_formulas() {
+        let mut solver = Solver::new();
+        let (a, b, c) = (solver.new_var(), solver.new_var(), solver.new_var());
+        let clauses = vec![
+            vec![a, b],
+            vec![-a, c],
+            vec![-b, -c],
+            vec![-c, -a, b],
+            vec![a],
+        ];
+        for clause in &clauses {
+            solver.add_clause(clause);
+        }
+        assert_eq!(solver.solve(), None);
+
+        let mut solver = Solver::new();
+        let (a, b, c) = (solver.new_var(), solver.new_var(), solver.new_var());
+        let clauses = vec![vec![a, b], vec![-a, c], vec![-b, -c], vec![a, -a]];
+        for clause in &clauses {
+            solver.add_clause(clause);
+        }
+        let model = solver.solve().unwrap();
+        assert!(satisfies(&clauses, &model));
+
+        let mut solver = Solver::new();
+        solver.add_clause(&[]);
+        assert_eq!(solver.solve(), None);
+    }
+
+    #[test]
+    fn proves_the_pigeonhole_principle() {
+        // 5 pigeons don't fit into 4 holes
+        let mut solver = Solver::new();
+        let holes = 4;
+        let sits: Vec<Vec<Lit>> = (0..=holes)
+            .map(|_| (0..holes).map(|_| solver.new_var()).collect())
+            .collect();
+        for pigeon in &sits {
+            solver.add_clause(pigeon);
+        }
+        for hole in 0..holes {
+            for first in 0..sits.len() {
+                for second in first + 1..sits.len() {
+                    solver.add_clause(&[-sits[first][hole], -sits[second][hole]]);
+                }
+            }
+        }
+        assert_eq!(solver.solve(), None);
+    }
+
+    #[test]
+    fn agrees_with_brute_force() {
+        // pseudo-random 3-SAT around the satisfiability threshold
+        let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound
+        };
+        for _ in 0..200 {
+            let variables = 8;
+            let clauses: Vec<Vec<Lit>> = (0..34)
+                .map(|_| {
+                    (0..3)
+                        .map(|_| {
+                            let lit = next(variables) as Lit + 1;
+                            if next(2) == 0 {
+                                -lit
+                            } else {
+                                lit
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+
+            let brute_force = (0..1u32 << variables).any(|bits| {
+                let model: Vec<bool> = (0..=variables)
+                    .map(|v| v > 0 && bits >> (v - 1) & 1 == 1)
+                    .collect();
+                satisfies(&clauses, &model)
+            });
+
+            let mut solver = Solver::new();
+            (0..variables).for_each(|_| {
+                solver.new_var();
+            });
+            for clause in &clauses {
+                solver.add_clause(clause);
+            }
+            match solver.solve() {
+                Some(model) => assert!(brute_force && satisfies(&clauses, &model)),
+                None => assert!(!brute_force),
+            }
+        }
+    }
+}
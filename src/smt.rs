@@ -0,0 +1,338 @@
+use std::fmt;
+
+use crate::config::SearchConfig;
+use crate::cpu::{validate, ExecError, Instruction};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmtError {
+    Exec(ExecError),
+    // only straight-line code is translated
+    Jump { instruction: usize },
+    UnsupportedOperation(String),
+    ValueTooWide { value: usize, width: u32 },
+}
+
+impl fmt::Display for SmtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SmtError::Exec(error) => write!(f, "{}", error),
+            SmtError::Jump { instruction } => {
+                write!(
+                    f,
+                    "Instruction {} jumps, which SMT-LIB2 export doesn't support",
+                    instruction
+                )
+            }
+            SmtError::UnsupportedOperation(operation) => {
+                write!(f, "SMT-LIB2 export doesn't support {}", operation)
+            }
+            SmtError::ValueTooWide { value, width } => {
+                write!(f, "{} doesn't fit into {} bits", value, width)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SmtError {}
+
+fn sort(width: u32) -> String {
+    format!("(_ BitVec {})", width)
+}
+
+// `value` modulo 2 ^ width
+fn literal(value: usize, width: u32) -> String {
+    let value = match width < usize::BITS {
+        true => value & ((1 << width) - 1),
+        false => value,
+    };
+    format!("(_ bv{} {})", value, width)
+}
+
+fn fits(value: usize, width: u32) -> bool {
+    value.checked_shr(width).unwrap_or(0) == 0
+}
+
+// the cells `instruction` writes and the terms they get, in terms of what the cells held before;
+// `None` for jumps
+fn assignments(
+    instruction: &Instruction,
+    state: &[String],
+    width: u32,
+) -> Option<Vec<(usize, String)>> {
+    let unary = |op: &str, a: usize| vec![(a, format!("({} {})", op, state[a]))];
+    let binary =
+        |op: &str, a: usize, b: usize| vec![(a, format!("({} {} {})", op, state[a], state[b]))];
+    let one = literal(1, width);
+
+    Some(match *instruction {
+        Instruction::Load(value) => vec![(0, literal(value, width))],
+        Instruction::LoadTo(memory, value) => vec![(memory, literal(value, width))],
+        Instruction::Swap(memory1, memory2) if memory1 == memory2 => vec![],
+        Instruction::Swap(memory1, memory2) => vec![
+            (memory1, state[memory2].clone()),
+            (memory2, state[memory1].clone()),
+        ],
+        Instruction::Mov(memory1, memory2) => vec![(memory1, state[memory2].clone())],
+        Instruction::Xor(memory1, memory2) => binary("bvxor", memory1, memory2),
+        Instruction::Add(memory1, memory2) => binary("bvadd", memory1, memory2),
+        Instruction::Sub(memory1, memory2) => binary("bvsub", memory1, memory2),
+        Instruction::And(memory1, memory2) => binary("bvand", memory1, memory2),
+        Instruction::Or(memory1, memory2) => binary("bvor", memory1, memory2),
+        // like the CPU, shifting by the width or more clears the cell
+        Instruction::Shl(memory1, memory2) => binary("bvshl", memory1, memory2),
+        Instruction::Shr(memory1, memory2) => binary("bvlshr", memory1, memory2),
+        Instruction::Inc(memory) => vec![(memory, format!("(bvadd {} {})", state[memory], one))],
+        Instruction::Dec(memory) => vec![(memory, format!("(bvsub {} {})", state[memory], one))],
+        Instruction::Not(memory) => unary("bvnot", memory),
+        Instruction::Neg(memory) => unary("bvneg", memory),
+        Instruction::Jmp(_) | Instruction::Jz(_, _) | Instruction::Jnz(_, _) => return None,
+    })
+}
+
+// defines every value `program` computes as `{prefix}{step}_c{cell}`, starting from the terms in
+// `state`, which end up naming the final state
+fn define_program(
+    lines: &mut Vec<String>,
+    prefix: &str,
+    program: &[Instruction],
+    state: &mut [String],
+    width: u32,
+) -> Result<(), SmtError> {
+    for (index, instruction) in program.iter().enumerate() {
+        let assignments =
+            assignments(instruction, state, width).ok_or(SmtError::Jump { instruction: index })?;
+        lines.push(format!("; {}: {}", prefix, instruction));
+        for (cell, term) in assignments {
+            let name = format!("{}{}_c{}", prefix, index + 1, cell);
+            lines.push(format!("(define-fun {} () {} {})", name, sort(width), term));
+            state[cell] = name;
+        }
+    }
+    Ok(())
+}
+
+fn conjunction(terms: Vec<String>) -> String {
+    match terms.len() {
+        0 => "true".to_string(),
+        1 => terms[0].clone(),
+        _ => format!("(and {})", terms.join(" ")),
+    }
+}
+
+// a QF_BV query that is unsatisfiable exactly when `left` and `right` leave the same state on
+// every initial state of `cells` cells, computing modulo 2 ^ width; a model is a counterexample
+pub fn equivalence_query(
+    left: &[Instruction],
+    right: &[Instruction],
+    cells: usize,
+    width: u32,
+) -> Result<String, SmtError> {
+    validate(left, cells).map_err(SmtError::Exec)?;
+    validate(right, cells).map_err(SmtError::Exec)?;
+
+    let mut lines = vec![
+        format!(
+            "; left and right agree on every input of {} cells of {} bits iff this is unsat;",
+            cells, width
+        ),
+        "; a model is an input they disagree on".to_string(),
+        "(set-option :produce-models true)".to_string(),
+        "(set-logic QF_BV)".to_string(),
+    ];
+    let inputs: Vec<String> = (0..cells).map(|cell| format!("c{}", cell)).collect();
+    for input in &inputs {
+        lines.push(format!("(declare-const {} {})", input, sort(width)));
+    }
+
+    let mut left_state = inputs.clone();
+    define_program(&mut lines, "left", left, &mut left_state, width)?;
+    let mut right_state = inputs;
+    define_program(&mut lines, "right", right, &mut right_state, width)?;
+
+    let equalities = left_state
+        .iter()
+        .zip(&right_state)
+        .map(|(left, right)| format!("(= {} {})", left, right))
+        .collect();
+    lines.push(format!("(assert (not {}))", conjunction(equalities)));
+    lines.push("(check-sat)".to_string());
+    lines.push("(get-model)".to_string());
+    Ok(lines.join("\n"))
+}
+
+// a QF_BV query that is satisfiable exactly when some program of `length` instructions from
+// `config` takes the all-zero state to `target_state`, computing modulo 2 ^ width; the model's
+// `op1`, `op2`, ... pick the instructions by their number in the listing at the top
+pub fn synthesis_query(
+    config: &SearchConfig,
+    target_state: &[usize],
+    length: usize,
+    width: u32,
+) -> Result<String, SmtError> {
+    if let Some(&value) = target_state.iter().find(|&&value| !fits(value, width)) {
+        return Err(SmtError::ValueTooWide { value, width });
+    }
+    let cells = config.max_memory_cells;
+    let alphabet = config.instructions(length);
+    if let Some(jump) = alphabet
+        .iter()
+        .find(|instruction| instruction.jump_target().is_some())
+    {
+        return Err(SmtError::UnsupportedOperation(jump.operation()));
+    }
+
+    let selector_width = (usize::BITS - alphabet.len().saturating_sub(1).leading_zeros()).max(1);
+    let mut lines = vec![
+        format!(
+            "; a program of {} instructions taking {} zeroed cells of {} bits to {:?} exists iff this is sat;",
+            length, cells, width, target_state
+        ),
+        "; each op picks one of these instructions:".to_string(),
+    ];
+    for (number, instruction) in alphabet.iter().enumerate() {
+        lines.push(format!(";   {}: {}", number, instruction));
+    }
+    lines.push("(set-option :produce-models true)".to_string());
+    lines.push("(set-logic QF_BV)".to_string());
+
+    let selectors: Vec<String> = (1..=length).map(|slot| format!("op{}", slot)).collect();
+    for selector in &selectors {
+        lines.push(format!(
+            "(declare-const {} {})",
+            selector,
+            sort(selector_width)
+        ));
+        if fits(alphabet.len(), selector_width) {
+            lines.push(format!(
+                "(assert (bvult {} {}))",
+                selector,
+                literal(alphabet.len(), selector_width)
+            ));
+        }
+    }
+
+    let mut state: Vec<String> = (0..cells).map(|_| literal(0, width)).collect();
+    for (step, selector) in selectors.iter().enumerate() {
+        // each cell keeps its value unless the chosen instruction writes it
+        let mut terms = state.clone();
+        for (number, instruction) in alphabet.iter().enumerate().rev() {
+            let chosen = format!("(= {} {})", selector, literal(number, selector_width));
+            for (cell, term) in assignments(instruction, &state, width).unwrap_or_default() {
+                terms[cell] = format!("(ite {} {} {})", chosen, term, terms[cell]);
+            }
+        }
+        for (cell, term) in terms.into_iter().enumerate() {
+            let name = format!("s{}_c{}", step + 1, cell);
+            lines.push(format!("(define-fun {} () {} {})", name, sort(width), term));
+            state[cell] = name;
+        }
+    }
+
+    for (&value, cell) in target_state.iter().zip(&state) {
+        lines.push(format!("(assert (= {} {}))", cell, literal(value, width)));
+    }
+    lines.push("(check-sat)".to_string());
+    if !selectors.is_empty() {
+        lines.push(format!("(get-value ({}))", selectors.join(" ")));
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::InstructionSet;
+    use crate::parser::parse;
+
+    #[test]
+    fn exports_equivalence_queries() {
+        let left = parse("XOR 0, 0\nSWAP 0, 1\nINC 1").unwrap();
+        let right = parse("MOV 0, 1\nLOADTO 1, 1").unwrap();
+        let expected = "\
+; left and right agree on every input of 2 cells of 8 bits iff this is unsat;
+; a model is an input they disagree on
+(set-option :produce-models true)
+(set-logic QF_BV)
+(declare-const c0 (_ BitVec 8))
+(declare-const c1 (_ BitVec 8))
+; left: XOR 0, 0
+(define-fun left1_c0 () (_ BitVec 8) (bvxor c0 c0))
+; left: SWAP 0, 1
+(define-fun left2_c0 () (_ BitVec 8) c1)
+(define-fun left2_c1 () (_ BitVec 8) left1_c0)
+; left: INC 1
+(define-fun left3_c1 () (_ BitVec 8) (bvadd left2_c1 (_ bv1 8)))
+; right: MOV 0, 1
+(define-fun right1_c0 () (_ BitVec 8) c1)
+; right: LOADTO 1, 1
+(define-fun right2_c1 () (_ BitVec 8) (_ bv1 8))
+(assert (not (and (= left2_c0 right1_c0) (= left3_c1 right2_c1))))
+(check-sat)
+(get-model)";
+        assert_eq!(equivalence_query(&left, &right, 2, 8).unwrap(), expected);
+
+        let expected = "\
+; left and right agree on every input of 1 cells of 4 bits iff this is unsat;
+; a model is an input they disagree on
+(set-option :produce-models true)
+(set-logic QF_BV)
+(declare-const c0 (_ BitVec 4))
+; left: LOAD 17
+(define-fun left1_c0 () (_ BitVec 4) (_ bv1 4))
+(assert (not (= left1_c0 c0)))
+(check-sat)
+(get-model)";
+        let load = vec![Instruction::Load(17)];
+        assert_eq!(equivalence_query(&load, &[], 1, 4).unwrap(), expected);
+    }
+
+    #[test]
+    fn exports_synthesis_queries() {
+        let config =
+            SearchConfig::new(2, 1, 2).with_instruction_set(InstructionSet::new(&["LOAD", "INC"]));
+        let expected = "\
+; a program of 2 instructions taking 1 zeroed cells of 4 bits to [2] exists iff this is sat;
+; each op picks one of these instructions:
+;   0: LOAD 0
+;   1: LOAD 1
+;   2: INC 0
+(set-option :produce-models true)
+(set-logic QF_BV)
+(declare-const op1 (_ BitVec 2))
+(assert (bvult op1 (_ bv3 2)))
+(declare-const op2 (_ BitVec 2))
+(assert (bvult op2 (_ bv3 2)))
+(define-fun s1_c0 () (_ BitVec 4) (ite (= op1 (_ bv0 2)) (_ bv0 4) (ite (= op1 (_ bv1 2)) (_ bv1 4) (ite (= op1 (_ bv2 2)) (bvadd (_ bv0 4) (_ bv1 4)) (_ bv0 4)))))
+(define-fun s2_c0 () (_ BitVec 4) (ite (= op2 (_ bv0 2)) (_ bv0 4) (ite (= op2 (_ bv1 2)) (_ bv1 4) (ite (= op2 (_ bv2 2)) (bvadd s1_c0 (_ bv1 4)) s1_c0))))
+(assert (= s2_c0 (_ bv2 4)))
+(check-sat)
+(get-value (op1 op2))";
+        assert_eq!(synthesis_query(&config, &[2], 2, 4).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_translate() {
+        let looping = parse("INC 0\nJNZ 0, 0").unwrap();
+        assert_eq!(
+            equivalence_query(&looping, &[], 1, 8),
+            Err(SmtError::Jump { instruction: 1 })
+        );
+        assert!(matches!(
+            equivalence_query(&[Instruction::Inc(3)], &[], 1, 8),
+            Err(SmtError::Exec(ExecError::CellOutOfBounds { .. }))
+        ));
+
+        let config = SearchConfig::new(1, 1, 1).with_instruction_set(InstructionSet::new(&["JMP"]));
+        assert_eq!(
+            synthesis_query(&config, &[0], 1, 8),
+            Err(SmtError::UnsupportedOperation("JMP".to_string()))
+        );
+        assert_eq!(
+            synthesis_query(&SearchConfig::new(1, 1, 1), &[16], 1, 4),
+            Err(SmtError::ValueTooWide {
+                value: 16,
+                width: 4
+            })
+        );
+    }
+}
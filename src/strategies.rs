@@ -0,0 +1,57 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::cpu::Instruction;
+
+// any instruction over `cells` cells that doesn't jump
+pub fn straight_line(cells: usize) -> BoxedStrategy<Instruction> {
+    let cell = 0..cells;
+    let pair = (0..cells, 0..cells);
+    prop_oneof![
+        any::<usize>().prop_map(Instruction::Load),
+        (cell.clone(), any::<usize>())
+            .prop_map(|(memory, value)| Instruction::LoadTo(memory, value)),
+        pair.clone().prop_map(|(a, b)| Instruction::Swap(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::Xor(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::Add(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::Sub(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::And(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::Or(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::Shl(a, b)),
+        pair.clone().prop_map(|(a, b)| Instruction::Shr(a, b)),
+        pair.prop_map(|(a, b)| Instruction::Mov(a, b)),
+        cell.clone().prop_map(Instruction::Inc),
+        cell.clone().prop_map(Instruction::Dec),
+        cell.clone().prop_map(Instruction::Not),
+        cell.prop_map(Instruction::Neg),
+    ]
+    .boxed()
+}
+
+// any instruction over `cells` cells, jumping no further than the end of a `length` long program
+pub fn instruction(cells: usize, length: usize) -> BoxedStrategy<Instruction> {
+    let target = 0..=length;
+    prop_oneof![
+        6 => straight_line(cells),
+        1 => target.clone().prop_map(Instruction::Jmp),
+        1 => (0..cells, target.clone()).prop_map(|(memory, target)| Instruction::Jz(memory, target)),
+        1 => (0..cells, target).prop_map(|(memory, target)| Instruction::Jnz(memory, target)),
+    ]
+    .boxed()
+}
+
+// programs of up to `max_length` instructions whose jumps all land within them
+pub fn program(cells: usize, max_length: usize) -> impl Strategy<Value = Vec<Instruction>> {
+    (0..=max_length).prop_flat_map(move |length| vec(instruction(cells, length), length))
+}
+
+pub fn straight_line_program(
+    cells: usize,
+    max_length: usize,
+) -> impl Strategy<Value = Vec<Instruction>> {
+    vec(straight_line(cells), 0..=max_length)
+}
+
+pub fn state(cells: usize) -> impl Strategy<Value = Vec<usize>> {
+    vec(any::<usize>(), cells)
+}
@@ -1,71 +1,80 @@
 use crate::{
+    config::SearchConfig,
     cpu::{Instruction, CPU},
-    iters::product,
+    isa::InstructionSemantics,
+    iters::product_iter,
 };
 
-use strum::IntoEnumIterator;
+// a search given up on because its caller said so, before it found a program or ran out of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stopped;
 
-pub fn generate_and_search_programs(
-    max_instructions_length: usize,
-    max_memory_cells: usize,
-    max_value: usize,
-    tester: impl Fn(&Vec<Instruction>) -> bool,
-) -> Option<Vec<Instruction>> {
+pub fn generate_and_search_programs<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    tester: impl Fn(&Vec<I>) -> bool,
+) -> Option<Vec<I>> {
+    generate_and_search_programs_while(config, tester, || true).unwrap_or_default()
+}
+
+// the same search, asking `proceed` before each candidate whether to go on
+pub fn generate_and_search_programs_while<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    tester: impl Fn(&Vec<I>) -> bool,
+    mut proceed: impl FnMut() -> bool,
+) -> Result<Option<Vec<I>>, Stopped> {
     let mut count = 0;
 
     // iterating over all possible program sizes
-    for instructions_length in 1..=max_instructions_length {
-        // unique operations
-        let operations: Vec<String> = Instruction::iter()
-            .map(|instruction| instruction.operation())
-            .collect();
-
-        let possible_instructions = operations
-            .into_iter()
-            .flat_map(|operation| match operation.as_str() {
-                "LOAD" => (0..max_value)
-                    .map(|value| Instruction::Load(value))
-                    .collect::<Vec<_>>(),
-                "SWAP" => product(&(0..max_memory_cells).collect::<Vec<_>>(), 2)
-                    .iter()
-                    .map(|cells| Instruction::Swap(cells[0], cells[1]))
-                    .collect::<Vec<_>>(),
-                "XOR" => product(&(0..max_memory_cells).collect::<Vec<_>>(), 2)
-                    .iter()
-                    .map(|cells| Instruction::Xor(cells[0], cells[1]))
-                    .collect::<Vec<_>>(),
-                "INC" => (0..max_memory_cells)
-                    .map(|cell| Instruction::Inc(cell))
-                    .collect::<Vec<_>>(),
-                _ => panic!("Unknown operation: {}", operation),
-            })
-            .collect::<Vec<_>>();
+    for instructions_length in 1..=config.max_instructions_length {
+        // every enabled instruction with every possible argument
+        let possible_instructions = config.instructions(instructions_length);
 
         // iterating over all possible instruction combinations
-        for instruction_combination in product(&possible_instructions, instructions_length) {
+        for instruction_combination in product_iter(&possible_instructions, instructions_length) {
+            if !proceed() {
+                return Err(Stopped);
+            }
             if tester(&instruction_combination) {
-                return Some(instruction_combination);
+                return Ok(Some(instruction_combination));
             }
             count += 1;
 
             if count % 100000 == 0 {
-                println!("[SUPEROPTIMIZER] Programs generated: {}", count);
+                eprintln!("[SUPEROPTIMIZER] Programs generated: {}", count);
             }
         }
     }
 
-    None
+    Ok(None)
 }
 
 pub fn superoptimize(
     max_instructions_length: usize,
     max_memory_cells: usize,
     max_value: usize,
-    target_state: &Vec<usize>,
+    target_state: &[usize],
 ) -> Option<Vec<Instruction>> {
-    let tester = |program: &Vec<Instruction>| {
-        let mut cpu = CPU::new(max_memory_cells);
-        cpu.execute(program);
+    let config = SearchConfig::new(max_instructions_length, max_memory_cells, max_value);
+    superoptimize_with_config(&config, target_state)
+}
+
+pub fn superoptimize_with_config<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    target_state: &[usize],
+) -> Option<Vec<I>> {
+    superoptimize_while(config, target_state, || true).unwrap_or_default()
+}
+
+pub fn superoptimize_while<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    target_state: &[usize],
+    proceed: impl FnMut() -> bool,
+) -> Result<Option<Vec<I>>, Stopped> {
+    let tester = |program: &Vec<I>| {
+        let mut cpu = CPU::new(config.max_memory_cells).with_max_steps(config.max_steps);
+        if cpu.try_execute(program).is_err() {
+            return false;
+        }
         let state = cpu.state.clone();
 
         // check if the state is deep equal to the target state
@@ -77,5 +86,83 @@ pub fn superoptimize(
         result
     };
 
-    generate_and_search_programs(max_instructions_length, max_memory_cells, max_value, tester)
+    generate_and_search_programs_while(config, tester, proceed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{from_signed, to_signed, InstructionSet};
+
+    #[test]
+    fn can_superoptimize_with_extended_instruction_set() {
+        let target_state = vec![usize::MAX, 2, 0];
+        assert_eq!(superoptimize(3, 3, 3, &target_state), None);
+
+        let config = SearchConfig::new(3, 3, 3)
+            .with_instruction_set(InstructionSet::default().with("DEC").with("MOV"));
+        let program = superoptimize_with_config(&config, &target_state).unwrap();
+        let mut cpu = CPU::new(3);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, target_state);
+    }
+
+    #[test]
+    fn can_superoptimize_loop() {
+        // without SWAP or MOV, cell 1 can only be counted up one INC at a time
+        let set = InstructionSet::new(&["LOAD", "INC", "DEC", "JNZ"]);
+        let config = SearchConfig::new(5, 2, 4)
+            .with_instruction_set(set)
+            .with_max_steps(20);
+        let target_state = vec![0, 6];
+
+        let program = superoptimize_with_config(&config, &target_state).unwrap();
+        assert!(program.len() < 6);
+        assert!(program.iter().any(|op| op.jump_target().is_some()));
+
+        let mut cpu = CPU::new(2);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, target_state);
+    }
+
+    #[test]
+    fn load_to_shortens_programs() {
+        let target_state = vec![0, 3, 2];
+        assert_eq!(superoptimize(2, 3, 4, &target_state), None);
+
+        let config = SearchConfig::new(2, 3, 4)
+            .with_instruction_set(InstructionSet::default().without("LOAD").with("LOADTO"));
+        let program = superoptimize_with_config(&config, &target_state).unwrap();
+        assert_eq!(program.len(), 2);
+
+        let mut cpu = CPU::new(3);
+        cpu.execute(&program);
+        assert_eq!(cpu.state, target_state);
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let config = SearchConfig::new(2, 2, 3);
+        let mut candidates = 0;
+        let stopped = superoptimize_while(&config, &[5, 0], || {
+            candidates += 1;
+            candidates <= 10
+        });
+        assert_eq!(stopped, Err(Stopped));
+        assert_eq!(candidates, 11);
+        assert_eq!(superoptimize_while(&config, &[5, 0], || true), Ok(None));
+    }
+
+    #[test]
+    fn can_superoptimize_negative_target() {
+        let target_state = from_signed(&[0, -2, 1]);
+        let config = SearchConfig::new(3, 3, 3)
+            .with_instruction_set(InstructionSet::default().with("LOADTO").signed());
+        let program = superoptimize_with_config(&config, &target_state).unwrap();
+        assert_eq!(program.len(), 2);
+
+        let mut cpu = CPU::new(3);
+        cpu.execute(&program);
+        assert_eq!(to_signed(&cpu.state), vec![0, -2, 1]);
+    }
 }
@@ -1,53 +1,43 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use strum::IntoEnumIterator;
+
 use tokio::sync::mpsc;
 use tokio::task;
 
+use crate::config::SearchConfig;
 use crate::cpu::CPU;
-use crate::{cpu::Instruction, iters::product};
+use crate::isa::InstructionSemantics;
+use crate::{cpu::Instruction, iters::product_iter};
 
-pub async fn generate_and_search_programs(
-    max_instructions_length: usize,
-    max_memory_cells: usize,
-    max_value: usize,
-    target_state: &Vec<usize>,
-) -> Option<Vec<Instruction>> {
+// searches every length at once, but like the sequential search returns the shortest program,
+// and the first of that length in enumeration order
+pub async fn generate_and_search_programs<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    target_state: &[usize],
+) -> Option<Vec<I>> {
     let (sender, mut receiver) = mpsc::channel(1);
-    let target_state = Arc::new(target_state.clone());
-
-    for instructions_length in 1..=max_instructions_length {
-        let operations: Vec<String> = Instruction::iter()
-            .map(|instruction: Instruction| instruction.operation())
-            .collect();
-
-        let possible_instructions = operations
-            .into_iter()
-            .flat_map(|operation| match operation.as_str() {
-                "LOAD" => (0..max_value)
-                    .map(|value| Instruction::Load(value))
-                    .collect::<Vec<_>>(),
-                "SWAP" => product(&(0..max_memory_cells).collect::<Vec<_>>(), 2)
-                    .iter()
-                    .map(|cells| Instruction::Swap(cells[0], cells[1]))
-                    .collect::<Vec<_>>(),
-                "XOR" => product(&(0..max_memory_cells).collect::<Vec<_>>(), 2)
-                    .iter()
-                    .map(|cells| Instruction::Xor(cells[0], cells[1]))
-                    .collect::<Vec<_>>(),
-                "INC" => (0..max_memory_cells)
-                    .map(|cell| Instruction::Inc(cell))
-                    .collect::<Vec<_>>(),
-                _ => panic!("Unknown operation: {}", operation),
-            })
-            .collect::<Vec<_>>();
+    let target_state = Arc::new(target_state.to_vec());
+    let max_memory_cells = config.max_memory_cells;
+    let max_steps = config.max_steps;
+    // longer searches stop once a shorter program is known
+    let shortest = Arc::new(AtomicUsize::new(usize::MAX));
 
+    for instructions_length in 1..=config.max_instructions_length {
         let sender = sender.clone();
         let target_state = Arc::clone(&target_state);
+        let shortest = Arc::clone(&shortest);
+        let possible_instructions = config.instructions(instructions_length);
 
         task::spawn(async move {
-            for instruction_combination in product(&possible_instructions, instructions_length) {
-                let mut cpu = CPU::new(max_memory_cells);
-                cpu.execute(&instruction_combination);
+            for instruction_combination in product_iter(&possible_instructions, instructions_length)
+            {
+                if shortest.load(Ordering::Relaxed) < instructions_length {
+                    return;
+                }
+                let mut cpu = CPU::new(max_memory_cells).with_max_steps(max_steps);
+                if cpu.try_execute(&instruction_combination).is_err() {
+                    continue;
+                }
                 let state = cpu.state.clone();
 
                 // check if the state is deep equal to the target state
@@ -57,6 +47,7 @@ pub async fn generate_and_search_programs(
                     .all(|(target_value, state_value)| target_value == state_value);
 
                 if program_found {
+                    shortest.fetch_min(instructions_length, Ordering::Relaxed);
                     sender.send(instruction_combination).await.unwrap();
                     return;
                 }
@@ -64,20 +55,33 @@ pub async fn generate_and_search_programs(
         });
     }
 
-    receiver.recv().await
+    // only the tasks hold senders now, so `recv` ends once they're all done
+    drop(sender);
+    let mut found: Option<Vec<I>> = None;
+    while let Some(program) = receiver.recv().await {
+        if found
+            .as_ref()
+            .is_none_or(|found| program.len() < found.len())
+        {
+            found = Some(program);
+        }
+    }
+    found
 }
 
 pub async fn superoptimize(
     max_instructions_length: usize,
     max_memory_cells: usize,
     max_value: usize,
-    target_state: &Vec<usize>,
+    target_state: &[usize],
 ) -> Option<Vec<Instruction>> {
-    generate_and_search_programs(
-        max_instructions_length,
-        max_memory_cells,
-        max_value,
-        target_state,
-    )
-    .await
+    let config = SearchConfig::new(max_instructions_length, max_memory_cells, max_value);
+    superoptimize_with_config(&config, target_state).await
+}
+
+pub async fn superoptimize_with_config<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    target_state: &[usize],
+) -> Option<Vec<I>> {
+    generate_and_search_programs(config, target_state).await
 }
@@ -1,52 +1,35 @@
+use crate::config::SearchConfig;
 use crate::cpu::CPU;
-use crate::{cpu::Instruction, iters::product};
-use std::sync::{mpsc, Arc};
+use crate::isa::InstructionSemantics;
+use crate::{cpu::Instruction, iters::product_iter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use rayon::prelude::*;
-use strum::IntoEnumIterator;
 
-pub fn generate_and_search_programs(
-    max_instructions_length: usize,
-    max_memory_cells: usize,
-    max_value: usize,
+// searches every length at once, but like the sequential search returns the shortest program,
+// and the first of that length in enumeration order
+pub fn generate_and_search_programs<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
     target_state: Arc<Vec<usize>>,
-) -> Option<Vec<Instruction>> {
-    let (sender, receiver) = mpsc::channel();
-
-    (1..=max_instructions_length).into_par_iter().for_each_with(
-        sender,
-        |sender, instructions_length| {
-            let operations: Vec<String> = Instruction::iter()
-                .map(|instruction: Instruction| instruction.operation())
-                .collect();
-
-            let possible_instructions = operations
-                .into_iter()
-                .flat_map(|operation| match operation.as_str() {
-                    "LOAD" => (0..max_value)
-                        .map(|value| Instruction::Load(value))
-                        .collect::<Vec<_>>(),
-                    "SWAP" => product(&(0..max_memory_cells).collect::<Vec<_>>(), 2)
-                        .iter()
-                        .map(|cells| Instruction::Swap(cells[0], cells[1]))
-                        .collect::<Vec<_>>(),
-                    "XOR" => product(&(0..max_memory_cells).collect::<Vec<_>>(), 2)
-                        .iter()
-                        .map(|cells| Instruction::Xor(cells[0], cells[1]))
-                        .collect::<Vec<_>>(),
-                    "INC" => (0..max_memory_cells)
-                        .map(|cell| Instruction::Inc(cell))
-                        .collect::<Vec<_>>(),
-                    _ => panic!("Unknown operation: {}", operation),
-                })
-                .collect::<Vec<_>>();
+) -> Option<Vec<I>> {
+    // longer searches stop once a shorter program is known
+    let shortest = AtomicUsize::new(usize::MAX);
 
-            let sender = sender.clone();
-            let target_state = Arc::clone(&target_state);
+    (1..=config.max_instructions_length)
+        .into_par_iter()
+        .find_map_first(|instructions_length| {
+            let possible_instructions = config.instructions(instructions_length);
 
-            for instruction_combination in product(&possible_instructions, instructions_length) {
-                let mut cpu = CPU::new(max_memory_cells);
-                cpu.execute(&instruction_combination);
+            for instruction_combination in product_iter(&possible_instructions, instructions_length)
+            {
+                if shortest.load(Ordering::Relaxed) < instructions_length {
+                    return None;
+                }
+                let mut cpu = CPU::new(config.max_memory_cells).with_max_steps(config.max_steps);
+                if cpu.try_execute(&instruction_combination).is_err() {
+                    continue;
+                }
                 let state = cpu.state.clone();
 
                 // check if the state is deep equal to the target state
@@ -56,26 +39,27 @@ pub fn generate_and_search_programs(
                     .all(|(target_value, state_value)| target_value == state_value);
 
                 if program_found {
-                    sender.send(Some(instruction_combination)).unwrap();
-                    return;
+                    shortest.fetch_min(instructions_length, Ordering::Relaxed);
+                    return Some(instruction_combination);
                 }
             }
-        },
-    );
-
-    receiver.iter().find_map(|res| res)
+            None
+        })
 }
 
 pub fn superoptimize(
     max_instructions_length: usize,
     max_memory_cells: usize,
     max_value: usize,
-    target_state: &Vec<usize>,
+    target_state: &[usize],
 ) -> Option<Vec<Instruction>> {
-    generate_and_search_programs(
-        max_instructions_length,
-        max_memory_cells,
-        max_value,
-        Arc::new(target_state.clone()),
-    )
+    let config = SearchConfig::new(max_instructions_length, max_memory_cells, max_value);
+    superoptimize_with_config(&config, target_state)
+}
+
+pub fn superoptimize_with_config<I: InstructionSemantics>(
+    config: &SearchConfig<I>,
+    target_state: &[usize],
+) -> Option<Vec<I>> {
+    generate_and_search_programs(config, Arc::new(target_state.to_vec()))
 }
@@ -0,0 +1,327 @@
+use std::fmt;
+
+use crate::config::SearchConfig;
+use crate::cpu::Instruction;
+use crate::sat::{Lit, Solver};
+
+// beyond this the formulas get too large to be worth solving
+pub const MAX_WIDTH: u32 = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SatError {
+    // only LOAD, SWAP, XOR and INC are bit-blasted
+    UnsupportedOperation(String),
+    // a target value doesn't fit into the word width
+    ValueTooWide { value: usize, width: u32 },
+}
+
+impl fmt::Display for SatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SatError::UnsupportedOperation(operation) => {
+                write!(f, "The SAT backend doesn't support {}", operation)
+            }
+            SatError::ValueTooWide { value, width } => {
+                write!(f, "{} doesn't fit into {} bits", value, width)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SatError {}
+
+// one variable per bit, least significant first
+type Word = Vec<Lit>;
+
+fn word(solver: &mut Solver, width: u32) -> Word {
+    (0..width).map(|_| solver.new_var()).collect()
+}
+
+fn bit(value: usize, index: usize) -> bool {
+    value.checked_shr(index as u32).unwrap_or(0) & 1 == 1
+}
+
+// guard -> x == y
+fn equal_when(solver: &mut Solver, guard: Lit, x: Lit, y: Lit) {
+    solver.add_clause(&[-guard, -x, y]);
+    solver.add_clause(&[-guard, x, -y]);
+}
+
+// guard -> x == y ^ z
+fn xor_when(solver: &mut Solver, guard: Lit, x: Lit, y: Lit, z: Lit) {
+    solver.add_clause(&[-guard, -x, y, z]);
+    solver.add_clause(&[-guard, -x, -y, -z]);
+    solver.add_clause(&[-guard, x, -y, z]);
+    solver.add_clause(&[-guard, x, y, -z]);
+}
+
+// a new variable equal to x & y
+fn and(solver: &mut Solver, x: Lit, y: Lit) -> Lit {
+    let z = solver.new_var();
+    solver.add_clause(&[-z, x]);
+    solver.add_clause(&[-z, y]);
+    solver.add_clause(&[z, -x, -y]);
+    z
+}
+
+// guard -> next == value + 1, which must not overflow the word
+fn increment_when(solver: &mut Solver, guard: Lit, next: &Word, value: &Word) {
+    // bit i flips when every bit below it is set
+    let mut carry: Option<Lit> = None;
+    for (&next_bit, &value_bit) in next.iter().zip(value) {
+        carry = Some(match carry {
+            None => {
+                equal_when(solver, guard, next_bit, -value_bit);
+                value_bit
+            }
+            Some(carry) => {
+                xor_when(solver, guard, next_bit, value_bit, carry);
+                and(solver, value_bit, carry)
+            }
+        });
+    }
+    if let Some(carry) = carry {
+        solver.add_clause(&[-guard, -carry]);
+    }
+}
+
+// a formula satisfied exactly by the programs of `length` instructions from `alphabet` that
+// leave the cells in `target_state`, and the selector of each instruction in each slot
+fn encode(
+    alphabet: &[Instruction],
+    cells: usize,
+    length: usize,
+    width: u32,
+    target_state: &[usize],
+) -> (Solver, Vec<Vec<Lit>>) {
+    let mut solver = Solver::new();
+    let mut state: Vec<Word> = (0..cells).map(|_| word(&mut solver, width)).collect();
+    for &lit in state.iter().flatten() {
+        solver.add_clause(&[-lit]);
+    }
+
+    let mut slots = Vec::with_capacity(length);
+    for _ in 0..length {
+        let next: Vec<Word> = (0..cells).map(|_| word(&mut solver, width)).collect();
+
+        // exactly one instruction per slot
+        let selectors: Vec<Lit> = alphabet.iter().map(|_| solver.new_var()).collect();
+        solver.add_clause(&selectors);
+        for (index, &first) in selectors.iter().enumerate() {
+            for &second in &selectors[index + 1..] {
+                solver.add_clause(&[-first, -second]);
+            }
+        }
+
+        for (instruction, &guard) in alphabet.iter().zip(&selectors) {
+            let written = match *instruction {
+                Instruction::Load(value) => {
+                    for (index, &lit) in next[0].iter().enumerate() {
+                        let lit = if bit(value, index) { lit } else { -lit };
+                        solver.add_clause(&[-guard, lit]);
+                    }
+                    vec![0]
+                }
+                Instruction::Swap(memory1, memory2) => {
+                    for index in 0..width as usize {
+                        equal_when(
+                            &mut solver,
+                            guard,
+                            next[memory1][index],
+                            state[memory2][index],
+                        );
+                        equal_when(
+                            &mut solver,
+                            guard,
+                            next[memory2][index],
+                            state[memory1][index],
+                        );
+                    }
+                    vec![memory1, memory2]
+                }
+                Instruction::Xor(memory1, memory2) => {
+                    for index in 0..width as usize {
+                        let (x, y, z) = (
+                            next[memory1][index],
+                            state[memory1][index],
+                            state[memory2][index],
+                        );
+                        match memory1 == memory2 {
+                            true => solver.add_clause(&[-guard, -x]),
+                            false => xor_when(&mut solver, guard, x, y, z),
+                        }
+                    }
+                    vec![memory1]
+                }
+                Instruction::Inc(memory) => {
+                    increment_when(&mut solver, guard, &next[memory], &state[memory]);
+                    vec![memory]
+                }
+                _ => unreachable!("unsupported instructions are rejected before encoding"),
+            };
+
+            // everything else stays as it was
+            for cell in (0..cells).filter(|cell| !written.contains(cell)) {
+                for index in 0..width as usize {
+                    equal_when(&mut solver, guard, next[cell][index], state[cell][index]);
+                }
+            }
+        }
+
+        slots.push(selectors);
+        state = next;
+    }
+
+    for (&value, word) in target_state.iter().zip(&state) {
+        for (index, &lit) in word.iter().enumerate() {
+            solver.add_clause(&[if bit(value, index) { lit } else { -lit }]);
+        }
+    }
+
+    (solver, slots)
+}
+
+// a program of exactly `length` instructions from `config` that leaves the cells in
+// `target_state` while every value it computes fits into `width` bits; `None` when the solver
+// proves no such program exists
+pub fn synthesize(
+    config: &SearchConfig,
+    target_state: &[usize],
+    length: usize,
+    width: u32,
+) -> Result<Option<Vec<Instruction>>, SatError> {
+    if let Some(&value) = target_state.iter().find(|&&value| !fits(value, width)) {
+        return Err(SatError::ValueTooWide { value, width });
+    }
+
+    let mut alphabet = Vec::new();
+    for instruction in config.instructions(length) {
+        match instruction {
+            // a constant wider than the word can't be part of a program within it
+            Instruction::Load(value) if !fits(value, width) => {}
+            Instruction::Load(_)
+            | Instruction::Swap(_, _)
+            | Instruction::Xor(_, _)
+            | Instruction::Inc(_) => alphabet.push(instruction),
+            _ => return Err(SatError::UnsupportedOperation(instruction.operation())),
+        }
+    }
+
+    let (mut solver, slots) = encode(
+        &alphabet,
+        config.max_memory_cells,
+        length,
+        width,
+        target_state,
+    );
+    let program = solver.solve().map(|model| {
+        slots
+            .iter()
+            .map(|selectors| {
+                let chosen = selectors.iter().position(|&lit| model[lit as usize]);
+                alphabet[chosen.expect("every slot holds an instruction")]
+            })
+            .collect()
+    });
+    Ok(program)
+}
+
+fn fits(value: usize, width: u32) -> bool {
+    value.checked_shr(width).unwrap_or(0) == 0
+}
+
+// wide enough for the target and every immediate, with a bit to spare for intermediate values
+pub fn width_for(config: &SearchConfig, target_state: &[usize]) -> Result<u32, SatError> {
+    let largest = target_state
+        .iter()
+        .copied()
+        .chain([config.max_value.saturating_sub(1)])
+        .max()
+        .unwrap_or(0);
+    let width = usize::BITS - largest.leading_zeros() + 1;
+    match width <= MAX_WIDTH {
+        true => Ok(width),
+        false => Err(SatError::ValueTooWide {
+            value: largest,
+            width: MAX_WIDTH,
+        }),
+    }
+}
+
+// the shortest program reaching `target_state`, trying one length after another; `None` means
+// every length up to the limit was proven impossible for values below `2 ^ width_for(..)`
+pub fn superoptimize_with_config(
+    config: &SearchConfig,
+    target_state: &[usize],
+) -> Result<Option<Vec<Instruction>>, SatError> {
+    let width = width_for(config, target_state)?;
+    for length in 1..=config.max_instructions_length {
+        if let Some(program) = synthesize(config, target_state, length, width)? {
+            return Ok(Some(program));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{InstructionSet, CPU};
+    use crate::superoptimizer;
+
+    fn reaches(program: &[Instruction], cells: usize, target_state: &[usize]) -> bool {
+        let mut cpu = CPU::new(cells);
+        cpu.execute(program);
+        target_state.iter().zip(&cpu.state).all(|(a, b)| a == b)
+    }
+
+    #[test]
+    fn agrees_with_brute_force() {
+        let config = SearchConfig::new(3, 2, 4);
+        for target_state in [[2, 0], [0, 3], [3, 3], [1, 2], [5, 0], [0, 0], [4, 1]] {
+            let expected = superoptimizer::superoptimize_with_config(&config, &target_state);
+            let found = superoptimize_with_config(&config, &target_state).unwrap();
+            assert_eq!(
+                found.as_ref().map(Vec::len),
+                expected.as_ref().map(Vec::len),
+                "{:?}",
+                target_state
+            );
+            if let Some(program) = found {
+                assert!(reaches(&program, 2, &target_state));
+            }
+        }
+    }
+
+    #[test]
+    fn proves_there_is_no_program() {
+        let config = SearchConfig::new(3, 2, 4);
+        assert_eq!(synthesize(&config, &[3, 3], 1, 4), Ok(None));
+        assert_eq!(synthesize(&config, &[3, 3], 0, 4), Ok(None));
+        assert_eq!(synthesize(&config, &[0, 0], 0, 4), Ok(Some(vec![])));
+
+        // three INCs overflow two bits
+        let config = SearchConfig::new(4, 1, 1).with_instruction_set(InstructionSet::new(&["INC"]));
+        assert_eq!(synthesize(&config, &[0], 4, 2), Ok(None));
+        assert_eq!(synthesize(&config, &[0], 4, 3), Ok(None));
+        assert_eq!(
+            synthesize(&config, &[3], 3, 2),
+            Ok(Some(vec![Instruction::Inc(0); 3]))
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_encode() {
+        let config =
+            SearchConfig::new(2, 2, 4).with_instruction_set(InstructionSet::new(&["LOAD", "ADD"]));
+        assert_eq!(
+            superoptimize_with_config(&config, &[2, 0]),
+            Err(SatError::UnsupportedOperation("ADD".to_string()))
+        );
+        assert_eq!(
+            synthesize(&SearchConfig::new(1, 1, 4), &[9], 1, 3),
+            Err(SatError::ValueTooWide { value: 9, width: 3 })
+        );
+        assert!(width_for(&SearchConfig::new(1, 1, 4), &[1 << 20]).is_err());
+    }
+}
@@ -0,0 +1,713 @@
+use std::fmt;
+
+use crate::cpu::{validate, ExecError, Instruction};
+use crate::isa::InstructionSemantics;
+use crate::verify;
+
+// formulas beyond this many nodes are given up on rather than tracked
+pub const MAX_NODES: usize = 10_000;
+
+// a cell's value as a function of the initial cells; built only through the operators
+// implemented below, which keep it simplified so that equal formulas mean equal functions
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Expr {
+    Const(usize),
+    // the initial value of a cell
+    Var(usize),
+    Neg(Box<Expr>),
+    // a term times a constant other than 0, 1 and -1
+    Mul(usize, Box<Expr>),
+    // the operand lists are flattened and sorted, with any constant last
+    Xor(Vec<Expr>),
+    Add(Vec<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Shl(Box<Expr>, Box<Expr>),
+    Shr(Box<Expr>, Box<Expr>),
+}
+
+// splits the operands of an associative operation into its other terms and the folded constant
+fn gather(
+    operands: Vec<Expr>,
+    unwrap: fn(Expr) -> Result<Vec<Expr>, Expr>,
+    identity: usize,
+    fold: fn(usize, usize) -> usize,
+) -> (Vec<Expr>, usize) {
+    let mut terms = Vec::new();
+    let mut constant = identity;
+    let mut pending = operands;
+    while let Some(operand) = pending.pop() {
+        match unwrap(operand) {
+            Ok(nested) => pending.extend(nested),
+            Err(Expr::Const(value)) => constant = fold(constant, value),
+            Err(term) => terms.push(term),
+        }
+    }
+    terms.sort();
+    (terms, constant)
+}
+
+// the single term, or the operation over the terms and the constant unless it's the identity
+fn assemble(
+    mut terms: Vec<Expr>,
+    constant: usize,
+    identity: usize,
+    wrap: fn(Vec<Expr>) -> Expr,
+) -> Expr {
+    if constant != identity || terms.is_empty() {
+        terms.push(Expr::Const(constant));
+    }
+    match terms.len() {
+        1 => terms.pop().unwrap(),
+        _ => wrap(terms),
+    }
+}
+
+fn shift(value: usize, amount: usize, shift: fn(usize, u32) -> Option<usize>) -> usize {
+    u32::try_from(amount)
+        .ok()
+        .and_then(|amount| shift(value, amount))
+        .unwrap_or(0)
+}
+
+fn xor(left: Expr, right: Expr) -> Expr {
+    let unwrap = |expr| match expr {
+        Expr::Xor(terms) => Ok(terms),
+        other => Err(other),
+    };
+    let (terms, constant) = gather(vec![left, right], unwrap, 0, |a, b| a ^ b);
+    // equal terms cancel in pairs
+    let mut kept: Vec<Expr> = Vec::new();
+    for term in terms {
+        match kept.last() == Some(&term) {
+            true => {
+                kept.pop();
+            }
+            false => kept.push(term),
+        }
+    }
+    assemble(kept, constant, 0, Expr::Xor)
+}
+
+// `term` times `coefficient`
+fn scale(term: Expr, coefficient: usize) -> Expr {
+    match (term, coefficient) {
+        (_, 0) => Expr::Const(0),
+        (term, 1) => term,
+        (Expr::Const(value), coefficient) => Expr::Const(value.wrapping_mul(coefficient)),
+        (Expr::Neg(inner), coefficient) => scale(*inner, coefficient.wrapping_neg()),
+        (Expr::Mul(inner, term), coefficient) => scale(*term, inner.wrapping_mul(coefficient)),
+        (Expr::Add(terms), coefficient) => terms
+            .into_iter()
+            .map(|term| scale(term, coefficient))
+            .fold(Expr::Const(0), add),
+        (term, usize::MAX) => Expr::Neg(Box::new(term)),
+        (term, coefficient) => Expr::Mul(coefficient, Box::new(term)),
+    }
+}
+
+fn add(left: Expr, right: Expr) -> Expr {
+    let unwrap = |expr| match expr {
+        Expr::Add(terms) => Ok(terms),
+        other => Err(other),
+    };
+    let (terms, constant) = gather(vec![left, right], unwrap, 0, usize::wrapping_add);
+    // like terms are combined, so `c0 + c0` is `2 * c0` and a term cancels its negation
+    let mut coefficients: Vec<(Expr, usize)> = Vec::new();
+    for term in terms {
+        let (term, coefficient) = match term {
+            Expr::Neg(inner) => (*inner, usize::MAX),
+            Expr::Mul(coefficient, inner) => (*inner, coefficient),
+            term => (term, 1),
+        };
+        match coefficients.iter_mut().find(|(other, _)| *other == term) {
+            Some((_, sum)) => *sum = sum.wrapping_add(coefficient),
+            None => coefficients.push((term, coefficient)),
+        }
+    }
+    let mut kept: Vec<Expr> = coefficients
+        .into_iter()
+        .filter(|(_, coefficient)| *coefficient != 0)
+        .map(|(term, coefficient)| scale(term, coefficient))
+        .collect();
+    kept.sort();
+    assemble(kept, constant, 0, Expr::Add)
+}
+
+fn sub(left: Expr, right: Expr) -> Expr {
+    add(left, neg(right))
+}
+
+fn neg(operand: Expr) -> Expr {
+    match operand {
+        Expr::Const(value) => Expr::Const(value.wrapping_neg()),
+        Expr::Neg(inner) => *inner,
+        other => scale(other, usize::MAX),
+    }
+}
+
+fn not(operand: Expr) -> Expr {
+    xor(operand, Expr::Const(usize::MAX))
+}
+
+fn and(left: Expr, right: Expr) -> Expr {
+    let unwrap = |expr| match expr {
+        Expr::And(terms) => Ok(terms),
+        other => Err(other),
+    };
+    let (mut terms, constant) = gather(vec![left, right], unwrap, usize::MAX, |a, b| a & b);
+    if constant == 0 {
+        return Expr::Const(0);
+    }
+    terms.dedup();
+    assemble(terms, constant, usize::MAX, Expr::And)
+}
+
+fn or(left: Expr, right: Expr) -> Expr {
+    let unwrap = |expr| match expr {
+        Expr::Or(terms) => Ok(terms),
+        other => Err(other),
+    };
+    let (mut terms, constant) = gather(vec![left, right], unwrap, 0, |a, b| a | b);
+    if constant == usize::MAX {
+        return Expr::Const(usize::MAX);
+    }
+    terms.dedup();
+    assemble(terms, constant, 0, Expr::Or)
+}
+
+fn shl(value: Expr, amount: Expr) -> Expr {
+    match (value, amount) {
+        (Expr::Const(value), Expr::Const(amount)) => {
+            Expr::Const(shift(value, amount, usize::checked_shl))
+        }
+        (value, Expr::Const(0)) => value,
+        (Expr::Const(0), _) => Expr::Const(0),
+        (_, Expr::Const(amount)) if amount >= usize::BITS as usize => Expr::Const(0),
+        (value, amount) => Expr::Shl(Box::new(value), Box::new(amount)),
+    }
+}
+
+fn shr(value: Expr, amount: Expr) -> Expr {
+    match (value, amount) {
+        (Expr::Const(value), Expr::Const(amount)) => {
+            Expr::Const(shift(value, amount, usize::checked_shr))
+        }
+        (value, Expr::Const(0)) => value,
+        (Expr::Const(0), _) => Expr::Const(0),
+        (_, Expr::Const(amount)) if amount >= usize::BITS as usize => Expr::Const(0),
+        (value, amount) => Expr::Shr(Box::new(value), Box::new(amount)),
+    }
+}
+
+impl Expr {
+    // the value for concrete initial cells
+    pub fn evaluate(&self, input: &[usize]) -> usize {
+        let fold = |terms: &[Expr], identity: usize, fold: fn(usize, usize) -> usize| {
+            terms
+                .iter()
+                .map(|term| term.evaluate(input))
+                .fold(identity, fold)
+        };
+        match self {
+            Expr::Const(value) => *value,
+            Expr::Var(cell) => input[*cell],
+            Expr::Neg(operand) => operand.evaluate(input).wrapping_neg(),
+            Expr::Mul(coefficient, term) => term.evaluate(input).wrapping_mul(*coefficient),
+            Expr::Xor(terms) => fold(terms, 0, |a, b| a ^ b),
+            Expr::Add(terms) => fold(terms, 0, usize::wrapping_add),
+            Expr::And(terms) => fold(terms, usize::MAX, |a, b| a & b),
+            Expr::Or(terms) => fold(terms, 0, |a, b| a | b),
+            Expr::Shl(value, amount) => shift(
+                value.evaluate(input),
+                amount.evaluate(input),
+                usize::checked_shl,
+            ),
+            Expr::Shr(value, amount) => shift(
+                value.evaluate(input),
+                amount.evaluate(input),
+                usize::checked_shr,
+            ),
+        }
+    }
+
+    // how many nodes the formula has
+    pub fn size(&self) -> usize {
+        let sum = |terms: &[Expr]| terms.iter().map(Expr::size).sum::<usize>();
+        1 + match self {
+            Expr::Const(_) | Expr::Var(_) => 0,
+            Expr::Neg(operand) | Expr::Mul(_, operand) => operand.size(),
+            Expr::Xor(terms) | Expr::Add(terms) | Expr::And(terms) | Expr::Or(terms) => sum(terms),
+            Expr::Shl(value, amount) | Expr::Shr(value, amount) => value.size() + amount.size(),
+        }
+    }
+
+    // whether it needs parentheses as an operand; prefix operators bind tightest, then
+    // multiplication
+    fn is_compound(&self) -> bool {
+        match self {
+            Expr::Const(_) | Expr::Var(_) | Expr::Neg(_) | Expr::Mul(_, _) => false,
+            Expr::Xor(terms) => terms.last() != Some(&Expr::Const(usize::MAX)),
+            _ => true,
+        }
+    }
+}
+
+macro_rules! operator {
+    ($trait:ident, $method:ident, $simplify:ident) => {
+        impl std::ops::$trait for Expr {
+            type Output = Expr;
+
+            fn $method(self, other: Expr) -> Expr {
+                $simplify(self, other)
+            }
+        }
+    };
+}
+
+operator!(BitXor, bitxor, xor);
+operator!(Add, add, add);
+operator!(Sub, sub, sub);
+operator!(BitAnd, bitand, and);
+operator!(BitOr, bitor, or);
+operator!(Shl, shl, shl);
+operator!(Shr, shr, shr);
+
+impl std::ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        neg(self)
+    }
+}
+
+impl std::ops::Not for Expr {
+    type Output = Expr;
+
+    fn not(self) -> Expr {
+        not(self)
+    }
+}
+
+// wraps compound operands in parentheses
+struct Operand<'a>(&'a Expr);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.is_compound() {
+            true => write!(f, "({})", self.0),
+            false => write!(f, "{}", self.0),
+        }
+    }
+}
+
+fn join(f: &mut fmt::Formatter, terms: &[Expr], separator: &str) -> fmt::Result {
+    for (index, term) in terms.iter().enumerate() {
+        if index > 0 {
+            write!(f, " {} ", separator)?;
+        }
+        write!(f, "{}", Operand(term))?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Var(cell) => write!(f, "c{}", cell),
+            Expr::Neg(operand) => write!(f, "-{}", Operand(operand)),
+            // negative coefficients are shown as such
+            Expr::Mul(coefficient, term) if *coefficient > usize::MAX / 2 => {
+                write!(f, "-{} * {}", coefficient.wrapping_neg(), Operand(term))
+            }
+            Expr::Mul(coefficient, term) => write!(f, "{} * {}", coefficient, Operand(term)),
+            // `x ^ MAX` reads better as `!x`
+            Expr::Xor(terms) if terms.last() == Some(&Expr::Const(usize::MAX)) => {
+                match &terms[..terms.len() - 1] {
+                    [term] => write!(f, "!{}", Operand(term)),
+                    rest => write!(f, "!({})", Expr::Xor(rest.to_vec())),
+                }
+            }
+            Expr::Xor(terms) => join(f, terms, "^"),
+            Expr::Add(terms) => {
+                for (index, term) in terms.iter().enumerate() {
+                    // subtractions and negative constants are shown as such
+                    let (sign, term) = match term {
+                        Expr::Neg(inner) => ("-", inner.as_ref().clone()),
+                        Expr::Mul(coefficient, inner) if *coefficient > usize::MAX / 2 => {
+                            ("-", Expr::Mul(coefficient.wrapping_neg(), inner.clone()))
+                        }
+                        Expr::Const(value) if *value > usize::MAX / 2 => {
+                            ("-", Expr::Const(value.wrapping_neg()))
+                        }
+                        term => ("+", term.clone()),
+                    };
+                    match (index, sign) {
+                        (0, "-") => write!(f, "-{}", Operand(&term))?,
+                        (0, _) => write!(f, "{}", Operand(&term))?,
+                        _ => write!(f, " {} {}", sign, Operand(&term))?,
+                    }
+                }
+                Ok(())
+            }
+            Expr::And(terms) => join(f, terms, "&"),
+            Expr::Or(terms) => join(f, terms, "|"),
+            Expr::Shl(value, amount) => write!(f, "{} << {}", Operand(value), Operand(amount)),
+            Expr::Shr(value, amount) => write!(f, "{} >> {}", Operand(value), Operand(amount)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolicError {
+    Exec(ExecError),
+    // a conditional jump on a value that depends on the input
+    SymbolicBranch { instruction: usize },
+    // the formula of `cell` grew past `MAX_NODES`
+    TooLarge { instruction: usize, cell: usize },
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Exec(error) => write!(f, "{}", error),
+            SymbolicError::SymbolicBranch { instruction } => write!(
+                f,
+                "Instruction {} branches on a value that depends on the input",
+                instruction
+            ),
+            SymbolicError::TooLarge { instruction, cell } => write!(
+                f,
+                "Instruction {} makes the formula of c{} too large to follow",
+                instruction, cell
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+// runs `program` on cells that start out as `c0`, `c1`, ... and returns each cell's formula;
+// conditional jumps are followed as long as their condition is a constant, for up to `max_steps`
+// instructions
+pub fn execute(
+    program: &[Instruction],
+    cells: usize,
+    max_steps: usize,
+) -> Result<Vec<Expr>, SymbolicError> {
+    validate(program, cells).map_err(SymbolicError::Exec)?;
+    let mut state = (0..cells).map(Expr::Var).collect::<Vec<Expr>>();
+    let mut program_counter = 0;
+    let mut steps = 0;
+
+    while let Some(instruction) = program.get(program_counter) {
+        if steps >= max_steps {
+            return Err(SymbolicError::Exec(ExecError::StepLimitExceeded { steps }));
+        }
+        steps += 1;
+        program_counter += 1;
+
+        let binary = |state: &mut Vec<Expr>, a: usize, b: usize, op: fn(Expr, Expr) -> Expr| {
+            state[a] = op(state[a].clone(), state[b].clone());
+        };
+        let unary = |state: &mut Vec<Expr>, a: usize, op: fn(Expr) -> Expr| {
+            state[a] = op(state[a].clone());
+        };
+        let branch = |state: &Vec<Expr>, cell: usize, when_zero: bool| match state[cell] {
+            Expr::Const(value) => Ok((value == 0) == when_zero),
+            _ => Err(SymbolicError::SymbolicBranch {
+                instruction: program_counter - 1,
+            }),
+        };
+
+        match *instruction {
+            Instruction::Load(value) => state[0] = Expr::Const(value),
+            Instruction::LoadTo(cell, value) => state[cell] = Expr::Const(value),
+            Instruction::Swap(a, b) => state.swap(a, b),
+            Instruction::Mov(a, b) => state[a] = state[b].clone(),
+            Instruction::Xor(a, b) => binary(&mut state, a, b, xor),
+            Instruction::Add(a, b) => binary(&mut state, a, b, add),
+            Instruction::Sub(a, b) => binary(&mut state, a, b, sub),
+            Instruction::And(a, b) => binary(&mut state, a, b, and),
+            Instruction::Or(a, b) => binary(&mut state, a, b, or),
+            Instruction::Shl(a, b) => binary(&mut state, a, b, shl),
+            Instruction::Shr(a, b) => binary(&mut state, a, b, shr),
+            Instruction::Inc(a) => state[a] = add(state[a].clone(), Expr::Const(1)),
+            Instruction::Dec(a) => state[a] = sub(state[a].clone(), Expr::Const(1)),
+            Instruction::Not(a) => unary(&mut state, a, not),
+            Instruction::Neg(a) => unary(&mut state, a, neg),
+            Instruction::Jmp(target) => program_counter = target,
+            Instruction::Jz(cell, target) => {
+                if branch(&state, cell, true)? {
+                    program_counter = target;
+                }
+            }
+            Instruction::Jnz(cell, target) => {
+                if branch(&state, cell, false)? {
+                    program_counter = target;
+                }
+            }
+        }
+
+        if let Some(cell) = instruction
+            .writes()
+            .into_iter()
+            .find(|&cell| state[cell].size() > MAX_NODES)
+        {
+            // only instructions that don't jump write
+            return Err(SymbolicError::TooLarge {
+                instruction: program_counter - 1,
+                cell,
+            });
+        }
+    }
+    Ok(state)
+}
+
+// one `cN = formula` line per cell
+pub fn explain(
+    program: &[Instruction],
+    cells: usize,
+    max_steps: usize,
+) -> Result<String, SymbolicError> {
+    let state = execute(program, cells, max_steps)?;
+    let lines = state
+        .iter()
+        .enumerate()
+        .map(|(cell, formula)| format!("c{} = {}", cell, formula))
+        .collect::<Vec<String>>();
+    Ok(lines.join("\n"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Equivalence {
+    // every cell ends up with the same formula
+    Proven,
+    // the formulas evaluate differently on this input
+    Refuted { input: Vec<usize> },
+    // the formulas of `cell` differ but agree on every input tried; simplification isn't
+    // complete, so they may still be equal
+    Unknown { cell: usize },
+}
+
+// inputs the formulas of differing cells are tried on before giving up
+pub fn probes(cells: usize) -> Vec<Vec<usize>> {
+    let patterns: [fn(usize) -> usize; 6] = [
+        |_| 0,
+        |_| 1,
+        |_| usize::MAX,
+        |cell| cell + 1,
+        |cell| (cell + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as usize),
+        |cell| 1 << (cell % usize::BITS as usize),
+    ];
+    patterns
+        .iter()
+        .map(|pattern| (0..cells).map(pattern).collect())
+        .collect()
+}
+
+pub fn equivalent(
+    left: &[Instruction],
+    right: &[Instruction],
+    cells: usize,
+    max_steps: usize,
+) -> Result<Equivalence, SymbolicError> {
+    let (left, right) = match (
+        execute(left, cells, max_steps),
+        execute(right, cells, max_steps),
+    ) {
+        (Ok(left), Ok(right)) => (left, right),
+        // formulas too large to compare can still be told apart by running the probes
+        (
+            Err(SymbolicError::TooLarge { cell, .. }),
+            Ok(_) | Err(SymbolicError::TooLarge { .. }),
+        )
+        | (Ok(_), Err(SymbolicError::TooLarge { cell, .. })) => {
+            let refuting = probes(cells).into_iter().find(|input| {
+                !verify::same(
+                    &verify::run(left, input, max_steps),
+                    &verify::run(right, input, max_steps),
+                )
+            });
+            return Ok(match refuting {
+                Some(input) => Equivalence::Refuted { input },
+                None => Equivalence::Unknown { cell },
+            });
+        }
+        (Err(error), _) | (_, Err(error)) => return Err(error),
+    };
+    let differing = (0..cells).find(|cell| left[*cell] != right[*cell]);
+    let cell = match differing {
+        Some(cell) => cell,
+        None => return Ok(Equivalence::Proven),
+    };
+
+    for input in probes(cells) {
+        let disagree = left
+            .iter()
+            .zip(&right)
+            .any(|(left, right)| left.evaluate(&input) != right.evaluate(&input));
+        if disagree {
+            return Ok(Equivalence::Refuted { input });
+        }
+    }
+    Ok(Equivalence::Unknown { cell })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_MAX_STEPS;
+    use crate::cpu::CPU;
+    use crate::parser::parse;
+
+    fn run(program: &[Instruction], cells: usize) -> Result<Vec<Expr>, SymbolicError> {
+        execute(program, cells, DEFAULT_MAX_STEPS)
+    }
+
+    #[test]
+    fn simplifies_while_executing() {
+        // the classic xor swap leaves a plain permutation
+        let swap = parse("XOR 0, 1\nXOR 1, 0\nXOR 0, 1").unwrap();
+        assert_eq!(run(&swap, 2), Ok(vec![Expr::Var(1), Expr::Var(0)]));
+        assert_eq!(Expr::Var(0) ^ Expr::Const(3) ^ Expr::Var(0), Expr::Const(3));
+        assert_eq!((Expr::Var(1) - Expr::Const(1)).to_string(), "c1 - 1");
+
+        let program = parse("INC 0\nINC 0\nDEC 1\nSUB 2, 2\nNOT 3\nNOT 3\nXOR 1, 0").unwrap();
+        assert_eq!(
+            explain(&program, 4, DEFAULT_MAX_STEPS).unwrap(),
+            "c0 = c0 + 2\nc1 = (c0 + 2) ^ (c1 - 1)\nc2 = 0\nc3 = c3"
+        );
+
+        let masks = parse("NOT 0\nAND 1, 0\nOR 2, 0\nLOADTO 3, 3\nSHL 1, 3\nNEG 2").unwrap();
+        assert_eq!(
+            explain(&masks, 4, DEFAULT_MAX_STEPS).unwrap(),
+            "c0 = !c0\nc1 = (c1 & !c0) << 3\nc2 = -(c2 | !c0)\nc3 = 3"
+        );
+    }
+
+    #[test]
+    fn formulas_agree_with_the_cpu() {
+        let program = parse("ADD 0, 1\nXOR 1, 0\nSHR 0, 2\nNEG 1\nOR 2, 1\nAND 0, 2").unwrap();
+        let formulas = run(&program, 3).unwrap();
+        for input in probes(3).into_iter().chain([vec![5, 9, 1], vec![7, 3, 2]]) {
+            let mut cpu = CPU::new(3);
+            cpu.state = input.clone();
+            cpu.execute(&program);
+            let evaluated: Vec<usize> = formulas.iter().map(|f| f.evaluate(&input)).collect();
+            assert_eq!(evaluated, cpu.state);
+        }
+    }
+
+    #[test]
+    fn decides_equivalence() {
+        let swap = parse("XOR 0, 1\nXOR 1, 0\nXOR 0, 1").unwrap();
+        let swapped = parse("SWAP 0, 1").unwrap();
+        assert_eq!(
+            equivalent(&swap, &swapped, 2, DEFAULT_MAX_STEPS),
+            Ok(Equivalence::Proven)
+        );
+        assert_eq!(
+            equivalent(&swap, &swapped, 1, DEFAULT_MAX_STEPS),
+            Err(SymbolicError::Exec(ExecError::CellOutOfBounds {
+                instruction: 0,
+                cell: 1
+            }))
+        );
+
+        // swapping through a scratch cell clobbers it, which only shows once it's non-zero
+        let movs = parse("MOV 2, 0\nMOV 0, 1\nMOV 1, 2\nLOADTO 2, 0").unwrap();
+        assert_eq!(
+            equivalent(&swap, &movs, 3, DEFAULT_MAX_STEPS),
+            Ok(Equivalence::Refuted {
+                input: vec![1, 1, 1]
+            })
+        );
+
+        let shifted = parse("SHL 0, 1").unwrap();
+        let ored = parse("SHL 0, 1\nOR 0, 0").unwrap();
+        assert_eq!(
+            equivalent(&shifted, &ored, 2, DEFAULT_MAX_STEPS),
+            Ok(Equivalence::Proven)
+        );
+
+        let left = parse("AND 0, 1\nOR 0, 1").unwrap();
+        let right = parse("MOV 0, 1").unwrap();
+        // absorption isn't one of the simplifications
+        assert_eq!(
+            equivalent(&left, &right, 2, DEFAULT_MAX_STEPS),
+            Ok(Equivalence::Unknown { cell: 0 })
+        );
+
+        let branching = parse("JZ 0, 2\nINC 1").unwrap();
+        assert_eq!(
+            run(&branching, 2),
+            Err(SymbolicError::SymbolicBranch { instruction: 0 })
+        );
+        let constant = parse("LOAD 0\nJZ 0, 3\nINC 1").unwrap();
+        assert_eq!(run(&constant, 2), Ok(vec![Expr::Const(0), Expr::Var(1)]));
+    }
+
+    #[test]
+    fn combines_like_terms() {
+        let doubled = parse("ADD 0, 0\nADD 0, 0\nSUB 0, 1\nADD 0, 1\nNEG 0").unwrap();
+        assert_eq!(
+            explain(&doubled, 2, DEFAULT_MAX_STEPS).unwrap(),
+            "c0 = -4 * c0\nc1 = c1"
+        );
+        assert_eq!(
+            (Expr::Var(0) - (Expr::Var(1) + Expr::Var(1)) + Expr::Var(0)).to_string(),
+            "2 * c0 - 2 * c1"
+        );
+        assert_eq!(
+            (Expr::Var(0) + Expr::Var(0) - Expr::Var(0) - Expr::Var(0)),
+            Expr::Const(0)
+        );
+
+        // each step used to double the formula
+        let source = ["INC 0", "ADD 0, 0"].repeat(40).join("\n");
+        let formulas = run(&parse(&source).unwrap(), 1).unwrap();
+        assert!(formulas[0].size() < 10);
+        // 2 ^ 200 * c1 + 2 ^ 200 - 1 wraps around to -1
+        let looping = parse("LOAD 200\nSWAP 0, 1\nADD 0, 0\nINC 0\nDEC 1\nJNZ 1, 2").unwrap();
+        assert_eq!(
+            run(&looping, 2),
+            Ok(vec![Expr::Const(usize::MAX), Expr::Const(0)])
+        );
+    }
+
+    #[test]
+    fn gives_up_on_formulas_too_large_to_follow() {
+        // xor and add don't combine, so the formula still doubles every step
+        let source = ["XOR 1, 0", "ADD 0, 1"].repeat(16).join("\n");
+        let program = parse(&source).unwrap();
+        assert!(matches!(
+            run(&program, 2),
+            Err(SymbolicError::TooLarge { .. })
+        ));
+
+        let mut changed = program.clone();
+        changed.push(Instruction::Inc(1));
+        assert!(matches!(
+            equivalent(&program, &changed, 2, DEFAULT_MAX_STEPS),
+            Ok(Equivalence::Refuted { .. })
+        ));
+        assert!(matches!(
+            equivalent(&program, &program, 2, DEFAULT_MAX_STEPS),
+            Ok(Equivalence::Unknown { .. })
+        ));
+        assert_eq!(
+            run(&parse("JMP 0").unwrap(), 1),
+            Err(SymbolicError::Exec(ExecError::StepLimitExceeded {
+                steps: 1000
+            }))
+        );
+        assert_eq!(
+            execute(&parse("JMP 0").unwrap(), 1, 5),
+            Err(SymbolicError::Exec(ExecError::StepLimitExceeded {
+                steps: 5
+            }))
+        );
+    }
+}
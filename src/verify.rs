@@ -0,0 +1,210 @@
+use std::fmt;
+
+use rayon::prelude::*;
+
+use crate::config::DEFAULT_MAX_STEPS;
+use crate::cpu::{ExecError, CPU};
+use crate::isa::InstructionSemantics;
+
+// the final state of a run, or why it didn't finish
+pub type Outcome = Result<Vec<usize>, ExecError>;
+
+// an initial state the two programs disagree on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub input: Vec<usize>,
+    pub left: Outcome,
+    pub right: Outcome,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outcome = |outcome: &Outcome| match outcome {
+            Ok(state) => format!("{:?}", state),
+            Err(error) => format!("fault ({})", error),
+        };
+        write!(
+            f,
+            "input {:?} gives {} vs {}",
+            self.input,
+            outcome(&self.left),
+            outcome(&self.right)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    Counterexample(Counterexample),
+    // `domain ^ cells` doesn't fit into a usize
+    TooManyInputs { cells: usize, domain: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::Counterexample(counterexample) => write!(f, "{}", counterexample),
+            VerifyError::TooManyInputs { cells, domain } => write!(
+                f,
+                "{} values in each of {} cells are too many inputs to check",
+                domain, cells
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+pub fn run<I: InstructionSemantics>(program: &[I], input: &[usize], max_steps: usize) -> Outcome {
+    let mut cpu = CPU::new(input.len()).with_max_steps(max_steps);
+    cpu.state.copy_from_slice(input);
+    cpu.try_execute(program).map(|_| cpu.state)
+}
+
+// both fault the same way, or both finish in the same state
+pub fn same(left: &Outcome, right: &Outcome) -> bool {
+    match (left, right) {
+        (Ok(left), Ok(right)) => left == right,
+        (Err(left), Err(right)) => std::mem::discriminant(left) == std::mem::discriminant(right),
+        _ => false,
+    }
+}
+
+// how many initial states have every cell hold a value from `domain`
+pub fn input_count(cells: usize, domain: &[usize]) -> Result<usize, VerifyError> {
+    u32::try_from(cells)
+        .ok()
+        .and_then(|cells| domain.len().checked_pow(cells))
+        .ok_or(VerifyError::TooManyInputs {
+            cells,
+            domain: domain.len(),
+        })
+}
+
+// the `index`th of the `domain.len() ^ cells` inputs, cell 0 varying slowest
+fn input_at(index: usize, cells: usize, domain: &[usize]) -> Vec<usize> {
+    let mut input = vec![0; cells];
+    let mut rest = index;
+    for cell in (0..cells).rev() {
+        input[cell] = domain[rest % domain.len()];
+        rest /= domain.len();
+    }
+    input
+}
+
+// checks every initial state whose cells each hold a value from `domain`; on failure returns
+// the first input that tells the programs apart, ordered cell by cell along `domain`
+pub fn equivalent<I: InstructionSemantics>(
+    left: &[I],
+    right: &[I],
+    cells: usize,
+    domain: &[usize],
+) -> Result<(), VerifyError> {
+    equivalent_within(left, right, cells, domain, DEFAULT_MAX_STEPS)
+}
+
+pub fn equivalent_within<I: InstructionSemantics>(
+    left: &[I],
+    right: &[I],
+    cells: usize,
+    domain: &[usize],
+    max_steps: usize,
+) -> Result<(), VerifyError> {
+    let inputs = input_count(cells, domain)?;
+
+    let counterexample = (0..inputs).into_par_iter().find_map_first(|index| {
+        let input = input_at(index, cells, domain);
+        let left = run(left, &input, max_steps);
+        let right = run(right, &input, max_steps);
+        match same(&left, &right) {
+            true => None,
+            false => Some(Counterexample { input, left, right }),
+        }
+    });
+
+    match counterexample {
+        Some(counterexample) => Err(VerifyError::Counterexample(counterexample)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Instruction;
+
+    fn distinguish<I: InstructionSemantics>(
+        left: &[I],
+        right: &[I],
+        cells: usize,
+        domain: &[usize],
+    ) -> Counterexample {
+        match equivalent(left, right, cells, domain) {
+            Err(VerifyError::Counterexample(counterexample)) => counterexample,
+            result => panic!("expected a counterexample, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn proves_equivalence_over_the_domain() {
+        // two ways of clearing cell 0 and swapping cells
+        let left = vec![Instruction::Xor(0, 0), Instruction::Swap(0, 1)];
+        let right = vec![Instruction::Mov(0, 1), Instruction::LoadTo(1, 0)];
+        assert_eq!(equivalent(&left, &right, 2, &[0, 1, 2, 3]), Ok(()));
+        assert_eq!(equivalent(&left, &left, 3, &[0, 7, usize::MAX]), Ok(()));
+    }
+
+    #[test]
+    fn finds_the_smallest_distinguishing_input() {
+        // they only agree while cell 1 is 0
+        let left = vec![Instruction::Inc(0)];
+        let right = vec![Instruction::Add(0, 1), Instruction::Inc(0)];
+        let counterexample = distinguish(&left, &right, 2, &[0, 1, 2]);
+        assert_eq!(counterexample.input, vec![0, 1]);
+        assert_eq!(counterexample.left, Ok(vec![1, 1]));
+        assert_eq!(counterexample.right, Ok(vec![2, 1]));
+        assert_eq!(
+            counterexample.to_string(),
+            "input [0, 1] gives [1, 1] vs [2, 1]"
+        );
+    }
+
+    #[test]
+    fn compares_faults() {
+        // loops forever unless cell 0 starts at 0
+        let looping = vec![Instruction::Jnz(0, 0)];
+        let counterexample = distinguish(&looping, &[], 1, &[0, 1]);
+        assert_eq!(counterexample.input, vec![1]);
+        assert!(matches!(
+            counterexample.left,
+            Err(ExecError::StepLimitExceeded { .. })
+        ));
+
+        // faults only match faults of the same kind
+        let out_of_bounds = vec![Instruction::Inc(5)];
+        let counterexample = distinguish(&out_of_bounds, &looping, 1, &[1]);
+        assert!(matches!(
+            counterexample.left,
+            Err(ExecError::CellOutOfBounds { .. })
+        ));
+        assert_eq!(
+            equivalent(&out_of_bounds, &[Instruction::Dec(3)], 1, &[1]),
+            Ok(())
+        );
+        assert_eq!(equivalent(&looping, &looping, 1, &[1]), Ok(()));
+    }
+
+    #[test]
+    fn refuses_domains_too_large_to_count() {
+        let program = vec![Instruction::Inc(0)];
+        assert_eq!(
+            equivalent(&program, &program, 64, &[0, 1, 2, 3]),
+            Err(VerifyError::TooManyInputs {
+                cells: 64,
+                domain: 4
+            })
+        );
+        assert_eq!(input_count(usize::MAX, &[0, 1]).ok(), None);
+        assert_eq!(input_count(3, &[0, 1]), Ok(8));
+    }
+}